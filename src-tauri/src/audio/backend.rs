@@ -0,0 +1,295 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Serialize, Clone)]
+pub struct AudioDevice {
+    pub id: String,
+    pub name: String,
+    pub direction: DeviceDirection,
+    pub state: DeviceState,
+    pub is_default: bool, // Default for the Multimedia role
+    /// Every role this device is currently the default for.
+    pub default_roles: Vec<DeviceRole>,
+    #[serde(flatten)]
+    pub metadata: DeviceMetadata,
+}
+
+/// Descriptive properties used for icons, grouping and telling apart devices
+/// with the same name. Backends fill in what they know.
+#[derive(Debug, Serialize, Clone, Default)]
+pub struct DeviceMetadata {
+    /// Short endpoint description, e.g. "Speakers".
+    pub description: Option<String>,
+    /// Name of the adapter the endpoint belongs to, e.g. "Realtek(R) Audio".
+    pub interface_name: Option<String>,
+    pub form_factor: DeviceFormFactor,
+    /// Shared by all endpoints of one physical device (a headset's speaker and mic).
+    pub container_id: Option<String>,
+    pub icon_path: Option<String>,
+}
+
+/// Mirrors the MMDevice `EndpointFormFactor` values.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Hash, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum DeviceFormFactor {
+    RemoteNetworkDevice,
+    Speakers,
+    LineLevel,
+    Headphones,
+    Microphone,
+    Headset,
+    Handset,
+    DigitalPassthrough,
+    Spdif,
+    Hdmi,
+    #[default]
+    Unknown,
+}
+
+impl AudioDevice {
+    pub fn is_default_for(&self, role: DeviceRole) -> bool {
+        self.default_roles.contains(&role)
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.state == DeviceState::Active
+    }
+}
+
+/// Mirrors the MMDevice `DEVICE_STATE_*` values. Only active devices can be
+/// made the default, but the others are still listed so they stay configurable.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum DeviceState {
+    Active,
+    Disabled,
+    NotPresent,
+    Unplugged,
+}
+
+/// Whether an endpoint plays audio (speakers, headphones) or records it
+/// (microphones).
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "lowercase")]
+pub enum DeviceDirection {
+    Render,
+    Capture,
+}
+
+impl DeviceDirection {
+    pub const ALL: [DeviceDirection; 2] = [DeviceDirection::Render, DeviceDirection::Capture];
+}
+
+/// The roles an endpoint can be the default for. Backends without a notion of
+/// roles treat every role as the same default.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "lowercase")]
+pub enum DeviceRole {
+    Console,
+    Multimedia,
+    Communications,
+}
+
+impl DeviceRole {
+    pub const ALL: [DeviceRole; 3] = [
+        DeviceRole::Console,
+        DeviceRole::Multimedia,
+        DeviceRole::Communications,
+    ];
+}
+
+/// Master volume of an endpoint.
+#[derive(Debug, Serialize, Clone, Copy, PartialEq)]
+pub struct VolumeInfo {
+    /// 0.0 to 1.0, on the same curve as the system volume slider.
+    pub scalar: f32,
+    pub db: f32,
+    pub muted: bool,
+}
+
+/// A volume to set, either as a slider position or in decibels. Sent from the
+/// frontend as `{ "scalar": 0.5 }` or `{ "db": -12.0 }`.
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum VolumeLevel {
+    Scalar(f32),
+    Db(f32),
+}
+
+/// A shared-mode stream format.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+pub struct AudioFormat {
+    pub sample_rate: u32,
+    /// Valid bits per sample, e.g. 24 for 24-bit audio in 32-bit containers.
+    pub bit_depth: u16,
+    pub channels: u16,
+    /// IEEE float samples rather than integer PCM.
+    #[serde(default)]
+    pub float: bool,
+}
+
+/// The formats of an endpoint, as on the Advanced tab of its properties.
+#[derive(Debug, Serialize, Clone)]
+pub struct DeviceFormats {
+    /// What shared-mode streams are currently converted to.
+    pub current: Option<AudioFormat>,
+    /// What `reset_device_format` goes back to.
+    pub default: Option<AudioFormat>,
+    /// The audio engine's internal mix format.
+    pub mix: Option<AudioFormat>,
+}
+
+/// Audio engine processing periods of an endpoint, in milliseconds.
+#[derive(Debug, Serialize, Clone, Copy, PartialEq)]
+pub struct ProcessingPeriod {
+    /// The period the engine runs at now.
+    pub current: f32,
+    pub default: f32,
+    /// The shortest period the driver supports.
+    pub minimum: f32,
+}
+
+/// Endpoint settings kept as device properties. Fields that are `None` were
+/// not reported by the device, and are left alone when setting.
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+#[serde(default)]
+pub struct EndpointProperties {
+    /// Audio enhancements (system effects) turned off.
+    pub disable_enhancements: Option<bool>,
+    /// "Listen to this device", for capture endpoints.
+    pub listen: Option<bool>,
+    /// Render endpoint a listened-to device plays through. An empty ID means
+    /// the default device.
+    pub listen_target: Option<String>,
+}
+
+impl EndpointProperties {
+    /// Copies every field `other` sets over this one.
+    pub fn merge(&mut self, other: &EndpointProperties) {
+        if other.disable_enhancements.is_some() {
+            self.disable_enhancements = other.disable_enhancements;
+        }
+        if other.listen.is_some() {
+            self.listen = other.listen;
+        }
+        if other.listen_target.is_some() {
+            self.listen_target = other.listen_target.clone();
+        }
+    }
+}
+
+/// Emitted to the frontend as `audio-device-event`, tagged by `kind`.
+#[derive(Debug, Serialize, Clone)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum DeviceEvent {
+    DefaultChanged {
+        id: String,
+        direction: DeviceDirection,
+        role: DeviceRole,
+    },
+    DeviceAdded {
+        id: String,
+    },
+    DeviceRemoved {
+        id: String,
+    },
+    StateChanged {
+        id: String,
+        state: DeviceState,
+    },
+    /// Volume or mute changed by something other than this app.
+    VolumeChanged {
+        id: String,
+        volume: VolumeInfo,
+    },
+    /// Something in the device list changed, for backends that can't say what.
+    DevicesChanged,
+}
+
+pub type EventCallback = Box<dyn Fn(DeviceEvent) + Send + Sync>;
+
+/// Everything the app needs from the platform audio stack.
+pub trait AudioBackend: Send + Sync {
+    /// Short identifier used in logs and error messages.
+    fn name(&self) -> &'static str;
+
+    /// Lists `direction` devices in every state, not only active ones.
+    fn enumerate_devices(&self, direction: DeviceDirection) -> Result<Vec<AudioDevice>, String>;
+
+    /// ID of the current default `direction` device for `role`, if there is one.
+    fn default_device(
+        &self,
+        direction: DeviceDirection,
+        role: DeviceRole,
+    ) -> Result<Option<String>, String>;
+
+    /// Makes `id` the default for `roles` within its own direction.
+    fn set_default_device(&self, id: &str, roles: &[DeviceRole]) -> Result<(), String>;
+
+    fn volume(&self, id: &str) -> Result<VolumeInfo, String>;
+
+    /// Sets the master volume of `id`. Scalars are clamped to 0.0..=1.0 and
+    /// decibels to the range the device supports.
+    fn set_volume(&self, id: &str, level: VolumeLevel) -> Result<(), String>;
+
+    fn set_mute(&self, id: &str, muted: bool) -> Result<(), String>;
+
+    fn device_formats(&self, _id: &str) -> Result<DeviceFormats, String> {
+        Err(unsupported(self.name(), "device formats"))
+    }
+
+    fn set_device_format(&self, _id: &str, _format: AudioFormat) -> Result<(), String> {
+        Err(unsupported(self.name(), "device formats"))
+    }
+
+    /// Goes back to the driver's default format.
+    fn reset_device_format(&self, _id: &str) -> Result<(), String> {
+        Err(unsupported(self.name(), "device formats"))
+    }
+
+    /// Whether applications may take exclusive control of `id`.
+    fn exclusive_mode(&self, _id: &str) -> Result<bool, String> {
+        Err(unsupported(self.name(), "exclusive mode"))
+    }
+
+    fn set_exclusive_mode(&self, _id: &str, _allowed: bool) -> Result<(), String> {
+        Err(unsupported(self.name(), "exclusive mode"))
+    }
+
+    fn processing_period(&self, _id: &str) -> Result<ProcessingPeriod, String> {
+        Err(unsupported(self.name(), "processing periods"))
+    }
+
+    /// Sets the engine period of `id`, in milliseconds. Must lie between the
+    /// minimum and the default period.
+    fn set_processing_period(&self, _id: &str, _period: f32) -> Result<(), String> {
+        Err(unsupported(self.name(), "processing periods"))
+    }
+
+    fn endpoint_properties(&self, _id: &str) -> Result<EndpointProperties, String> {
+        Err(unsupported(self.name(), "endpoint properties"))
+    }
+
+    /// Writes the fields of `properties` that are set.
+    fn set_endpoint_properties(
+        &self,
+        _id: &str,
+        _properties: &EndpointProperties,
+    ) -> Result<(), String> {
+        Err(unsupported(self.name(), "endpoint properties"))
+    }
+
+    /// Shows or hides `id` for the whole system, not only for this app. Hidden
+    /// endpoints are still enumerated, as disabled.
+    fn set_endpoint_visibility(&self, _id: &str, _visible: bool) -> Result<(), String> {
+        Err(unsupported(self.name(), "endpoint visibility"))
+    }
+
+    /// Registers a callback fired whenever the backend sees the device set or a
+    /// default change. Callbacks may run on any thread.
+    fn subscribe(&self, callback: EventCallback);
+}
+
+/// Error for the optional parts of the trait a backend doesn't implement.
+fn unsupported(backend: &str, feature: &str) -> String {
+    format!("The {} backend does not support {}", backend, feature)
+}
@@ -0,0 +1,188 @@
+use super::backend::{AudioBackend, AudioDevice, DeviceDirection, DeviceRole};
+
+pub struct CycleOutcome {
+    pub device: AudioDevice,
+    /// 1-based position of the new default in the included list. `None` when
+    /// everything was excluded and the cycle fell back to all devices.
+    pub display_number: Option<usize>,
+}
+
+/// Active devices that aren't excluded, in enumeration order.
+pub fn included_devices<'a>(
+    devices: &'a [AudioDevice],
+    excluded_ids: &[String],
+) -> Vec<&'a AudioDevice> {
+    devices
+        .iter()
+        .filter(|d| d.is_active() && !excluded_ids.contains(&d.id))
+        .collect()
+}
+
+/// The role whose default decides where a cycle over `roles` starts from and
+/// which number the tray shows.
+pub fn primary_role(roles: &[DeviceRole]) -> DeviceRole {
+    if roles.contains(&DeviceRole::Multimedia) {
+        DeviceRole::Multimedia
+    } else {
+        roles.first().copied().unwrap_or(DeviceRole::Multimedia)
+    }
+}
+
+/// The roles a cycle over `roles` switches; an empty list means every role.
+pub fn effective_roles(roles: &[DeviceRole]) -> &[DeviceRole] {
+    if roles.is_empty() {
+        &DeviceRole::ALL
+    } else {
+        roles
+    }
+}
+
+/// 1-based index of the current `role` default among the included devices, as
+/// shown on the tray icon.
+pub fn display_number(
+    devices: &[AudioDevice],
+    excluded_ids: &[String],
+    role: DeviceRole,
+) -> Option<usize> {
+    included_devices(devices, excluded_ids)
+        .iter()
+        .position(|d| d.is_default_for(role))
+        .map(|idx| idx + 1)
+}
+
+/// Picks the `direction` device after the current `roles` default among the
+/// included ones. Switching to it is left to the caller.
+pub fn next_device(
+    backend: &dyn AudioBackend,
+    direction: DeviceDirection,
+    roles: &[DeviceRole],
+    excluded_ids: &[String],
+) -> Result<CycleOutcome, String> {
+    let role = primary_role(effective_roles(roles));
+
+    let devices: Vec<AudioDevice> = backend
+        .enumerate_devices(direction)?
+        .into_iter()
+        .filter(|d| d.is_active())
+        .collect();
+    if devices.is_empty() {
+        return Err("No audio devices found".to_string());
+    }
+
+    let included = included_devices(&devices, excluded_ids);
+
+    // Fallback to all active devices if filtering removed everything or allowed nothing
+    let (candidates, fallback) = if included.is_empty() {
+        (devices.iter().collect::<Vec<_>>(), true)
+    } else {
+        (included, false)
+    };
+
+    let current_index = candidates.iter().position(|d| d.is_default_for(role));
+    let next_index = match current_index {
+        Some(idx) => (idx + 1) % candidates.len(),
+        None => 0,
+    };
+
+    Ok(CycleOutcome {
+        device: candidates[next_index].clone(),
+        display_number: if fallback { None } else { Some(next_index + 1) },
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::audio::backend::DeviceState;
+    use crate::audio::memory::MemoryBackend;
+
+    /// Cycles once and makes the result the default, like the hotkey does.
+    fn cycle(backend: &MemoryBackend, excluded_ids: &[&str]) -> CycleOutcome {
+        let excluded_ids: Vec<String> = excluded_ids.iter().map(|id| id.to_string()).collect();
+        let outcome = next_device(
+            backend,
+            DeviceDirection::Render,
+            &DeviceRole::ALL,
+            &excluded_ids,
+        )
+        .unwrap();
+        backend
+            .set_default_device(&outcome.device.id, &DeviceRole::ALL)
+            .unwrap();
+        outcome
+    }
+
+    #[test]
+    fn cycles_in_enumeration_order_and_wraps_around() {
+        let backend = MemoryBackend::speakers();
+        let order: Vec<(String, Option<usize>)> = (0..4)
+            .map(|_| cycle(&backend, &[]))
+            .map(|outcome| (outcome.device.id, outcome.display_number))
+            .collect();
+        assert_eq!(
+            order,
+            vec![
+                ("b".to_string(), Some(2)),
+                ("c".to_string(), Some(3)),
+                ("a".to_string(), Some(1)),
+                ("b".to_string(), Some(2)),
+            ]
+        );
+    }
+
+    #[test]
+    fn excluded_devices_are_skipped() {
+        let backend = MemoryBackend::speakers();
+        assert_eq!(cycle(&backend, &["b"]).device.id, "c");
+        let outcome = cycle(&backend, &["b"]);
+        assert_eq!(outcome.device.id, "a");
+        assert_eq!(outcome.display_number, Some(1));
+    }
+
+    #[test]
+    fn inactive_devices_are_skipped() {
+        let backend = MemoryBackend::speakers();
+        backend.set_device_state("b", DeviceState::Disabled);
+        backend.set_device_state("c", DeviceState::Unplugged);
+        // The only active device cycles onto itself
+        assert_eq!(cycle(&backend, &[]).device.id, "a");
+        assert_eq!(cycle(&backend, &[]).device.id, "a");
+    }
+
+    #[test]
+    fn falls_back_to_every_active_device_when_all_are_excluded() {
+        let backend = MemoryBackend::speakers();
+        let outcome = cycle(&backend, &["a", "b", "c"]);
+        assert_eq!(outcome.device.id, "b");
+        assert_eq!(outcome.display_number, None);
+    }
+
+    #[test]
+    fn starts_at_the_first_device_without_a_default() {
+        let backend = MemoryBackend::speakers();
+        backend.remove_device("a");
+        assert_eq!(cycle(&backend, &[]).device.id, "b");
+    }
+
+    #[test]
+    fn no_active_device_is_an_error() {
+        let backend = MemoryBackend::speakers();
+        for id in ["a", "b", "c"] {
+            backend.set_device_state(id, DeviceState::Disabled);
+        }
+        assert!(next_device(&backend, DeviceDirection::Render, &[], &[]).is_err());
+    }
+
+    #[test]
+    fn empty_roles_cycle_every_role_from_multimedia() {
+        assert_eq!(effective_roles(&[]), &DeviceRole::ALL);
+        assert_eq!(
+            primary_role(&[DeviceRole::Console, DeviceRole::Multimedia]),
+            DeviceRole::Multimedia
+        );
+        assert_eq!(
+            primary_role(&[DeviceRole::Communications]),
+            DeviceRole::Communications
+        );
+    }
+}
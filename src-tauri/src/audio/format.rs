@@ -0,0 +1,168 @@
+use super::backend::{AudioFormat, DeviceFormats};
+use super::policy::{policy_config, to_wide};
+use core::ffi::c_void;
+use windows::core::{GUID, HRESULT, PCWSTR};
+use windows::Win32::Media::Audio::{WAVEFORMATEX, WAVEFORMATEXTENSIBLE, WAVEFORMATEXTENSIBLE_0};
+use windows::Win32::System::Com::CoTaskMemFree;
+
+const WAVE_FORMAT_IEEE_FLOAT: u16 = 0x0003;
+const WAVE_FORMAT_EXTENSIBLE: u16 = 0xFFFE;
+const KSDATAFORMAT_SUBTYPE_PCM: GUID = GUID::from_u128(0x00000001_0000_0010_8000_00aa00389b71);
+const KSDATAFORMAT_SUBTYPE_IEEE_FLOAT: GUID =
+    GUID::from_u128(0x00000003_0000_0010_8000_00aa00389b71);
+
+/// Bytes of WAVEFORMATEXTENSIBLE that follow its WAVEFORMATEX header.
+const EXTENSIBLE_SIZE: u16 = 22;
+
+pub fn get_device_formats(device_id: &str) -> Result<DeviceFormats, String> {
+    let policy_config = policy_config()?;
+    let id_wide = to_wide(device_id);
+    let id_pcwstr = PCWSTR(id_wide.as_ptr());
+
+    unsafe {
+        Ok(DeviceFormats {
+            current: read_format(|format| policy_config.get_device_format(id_pcwstr, 0, format)),
+            default: read_format(|format| policy_config.get_device_format(id_pcwstr, 1, format)),
+            mix: read_format(|format| policy_config.get_mix_format(id_pcwstr, format)),
+        })
+    }
+}
+
+pub fn set_device_format(device_id: &str, format: AudioFormat) -> Result<(), String> {
+    validate(&format)?;
+
+    // The engine mixes in float at the device's rate and channel count
+    let mut endpoint_format = to_wave_format(&format);
+    let mut mix_format = to_wave_format(&AudioFormat {
+        bit_depth: 32,
+        float: true,
+        ..format
+    });
+
+    let policy_config = policy_config()?;
+    let id_wide = to_wide(device_id);
+    unsafe {
+        policy_config
+            .set_device_format(
+                PCWSTR(id_wide.as_ptr()),
+                &mut endpoint_format as *mut _ as *mut c_void,
+                &mut mix_format as *mut _ as *mut c_void,
+            )
+            .ok()
+            .map_err(|e| format!("Failed to set device format: {}", e))
+    }
+}
+
+pub fn reset_device_format(device_id: &str) -> Result<(), String> {
+    let policy_config = policy_config()?;
+    let id_wide = to_wide(device_id);
+    unsafe {
+        policy_config
+            .reset_device_format(PCWSTR(id_wide.as_ptr()))
+            .ok()
+            .map_err(|e| format!("Failed to reset device format: {}", e))
+    }
+}
+
+fn validate(format: &AudioFormat) -> Result<(), String> {
+    if !(8_000..=384_000).contains(&format.sample_rate) {
+        return Err(format!("Unsupported sample rate: {}", format.sample_rate));
+    }
+    if !(1..=8).contains(&format.channels) {
+        return Err(format!("Unsupported channel count: {}", format.channels));
+    }
+    let bit_depth_ok = if format.float {
+        format.bit_depth == 32
+    } else {
+        matches!(format.bit_depth, 16 | 24 | 32)
+    };
+    if !bit_depth_ok {
+        return Err(format!("Unsupported bit depth: {}", format.bit_depth));
+    }
+    Ok(())
+}
+
+/// Calls `get` for a CoTaskMemAlloc'd WAVEFORMATEX and parses it. `None` when
+/// the call fails, e.g. for a device that was never started.
+unsafe fn read_format(get: impl FnOnce(*mut *mut c_void) -> HRESULT) -> Option<AudioFormat> {
+    let mut ptr = std::ptr::null_mut();
+    if get(&mut ptr).is_err() || ptr.is_null() {
+        return None;
+    }
+    let format = parse_format(ptr as *const WAVEFORMATEX);
+    CoTaskMemFree(Some(ptr));
+    Some(format)
+}
+
+unsafe fn parse_format(ptr: *const WAVEFORMATEX) -> AudioFormat {
+    // The structs are packed, so copy them out instead of taking references
+    let format = ptr.read_unaligned();
+    let (bit_depth, float) =
+        if format.wFormatTag == WAVE_FORMAT_EXTENSIBLE && format.cbSize >= EXTENSIBLE_SIZE {
+            let extensible = (ptr as *const WAVEFORMATEXTENSIBLE).read_unaligned();
+            let valid_bits = { extensible.Samples }.wValidBitsPerSample;
+            (
+                if valid_bits == 0 {
+                    format.wBitsPerSample
+                } else {
+                    valid_bits
+                },
+                { extensible.SubFormat } == KSDATAFORMAT_SUBTYPE_IEEE_FLOAT,
+            )
+        } else {
+            (
+                format.wBitsPerSample,
+                format.wFormatTag == WAVE_FORMAT_IEEE_FLOAT,
+            )
+        };
+
+    AudioFormat {
+        sample_rate: format.nSamplesPerSec,
+        bit_depth,
+        channels: format.nChannels,
+        float,
+    }
+}
+
+fn to_wave_format(format: &AudioFormat) -> WAVEFORMATEXTENSIBLE {
+    // 24-bit audio is carried in 32-bit containers
+    let container_bits = if format.bit_depth == 24 {
+        32
+    } else {
+        format.bit_depth
+    };
+    let block_align = format.channels * container_bits / 8;
+
+    WAVEFORMATEXTENSIBLE {
+        Format: WAVEFORMATEX {
+            wFormatTag: WAVE_FORMAT_EXTENSIBLE,
+            nChannels: format.channels,
+            nSamplesPerSec: format.sample_rate,
+            nAvgBytesPerSec: format.sample_rate * block_align as u32,
+            nBlockAlign: block_align,
+            wBitsPerSample: container_bits,
+            cbSize: EXTENSIBLE_SIZE,
+        },
+        Samples: WAVEFORMATEXTENSIBLE_0 {
+            wValidBitsPerSample: format.bit_depth,
+        },
+        dwChannelMask: channel_mask(format.channels),
+        SubFormat: if format.float {
+            KSDATAFORMAT_SUBTYPE_IEEE_FLOAT
+        } else {
+            KSDATAFORMAT_SUBTYPE_PCM
+        },
+    }
+}
+
+/// The usual speaker layout for each channel count (KSAUDIO_SPEAKER_*).
+fn channel_mask(channels: u16) -> u32 {
+    match channels {
+        1 => 0x4,   // Mono: front center
+        2 => 0x3,   // Stereo
+        4 => 0x33,  // Quad
+        6 => 0x3F,  // 5.1
+        8 => 0x63F, // 7.1 surround
+        _ => 0,
+    }
+}
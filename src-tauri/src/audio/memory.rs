@@ -0,0 +1,298 @@
+use super::backend::{
+    AudioBackend, AudioDevice, DeviceDirection, DeviceEvent, DeviceMetadata, DeviceRole,
+    DeviceState, EventCallback, VolumeInfo, VolumeLevel,
+};
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+/// Decibel range of every memory device, mapped linearly onto the scalar.
+const MIN_DB: f32 = -65.25;
+const MAX_DB: f32 = 0.0;
+
+/// In-memory backend used where no real audio stack is available (CI, tests).
+/// Devices are added and removed by hand; defaults behave like WASAPI roles.
+#[derive(Default)]
+pub struct MemoryBackend {
+    state: Mutex<MemoryState>,
+    subscribers: Mutex<Vec<EventCallback>>,
+}
+
+#[derive(Default)]
+struct MemoryState {
+    devices: Vec<MemoryDevice>,
+    defaults: HashMap<(DeviceDirection, DeviceRole), String>,
+}
+
+struct MemoryDevice {
+    id: String,
+    name: String,
+    direction: DeviceDirection,
+    state: DeviceState,
+    volume: f32,
+    muted: bool,
+}
+
+impl MemoryDevice {
+    fn new(id: String, name: String, direction: DeviceDirection) -> Self {
+        Self {
+            id,
+            name,
+            direction,
+            state: DeviceState::Active,
+            volume: 0.5,
+            muted: false,
+        }
+    }
+
+    fn volume_info(&self) -> VolumeInfo {
+        VolumeInfo {
+            scalar: self.volume,
+            db: MIN_DB + self.volume * (MAX_DB - MIN_DB),
+            muted: self.muted,
+        }
+    }
+}
+
+impl MemoryBackend {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_devices<I, S>(devices: I) -> Self
+    where
+        I: IntoIterator<Item = (S, S, DeviceDirection)>,
+        S: Into<String>,
+    {
+        let backend = Self::new();
+        {
+            let mut state = backend.state.lock().unwrap();
+            for (id, name, direction) in devices {
+                state
+                    .devices
+                    .push(MemoryDevice::new(id.into(), name.into(), direction));
+            }
+            // Like Windows, the first endpoint of each direction becomes the
+            // default for every role
+            for direction in DeviceDirection::ALL {
+                let first = state
+                    .devices
+                    .iter()
+                    .find(|d| d.direction == direction)
+                    .map(|d| d.id.clone());
+                if let Some(id) = first {
+                    for role in DeviceRole::ALL {
+                        state.defaults.insert((direction, role), id.clone());
+                    }
+                }
+            }
+        }
+        backend
+    }
+
+    pub fn add_device(&self, id: &str, name: &str, direction: DeviceDirection) {
+        {
+            let mut state = self.state.lock().unwrap();
+            state.devices.retain(|d| d.id != id);
+            state.devices.push(MemoryDevice::new(
+                id.to_string(),
+                name.to_string(),
+                direction,
+            ));
+        }
+        self.notify(DeviceEvent::DeviceAdded { id: id.to_string() });
+    }
+
+    /// Simulates unplugging, disabling or re-enabling a device. A device that
+    /// leaves the active state stops being the default for anything.
+    pub fn set_device_state(&self, id: &str, device_state: DeviceState) {
+        {
+            let mut state = self.state.lock().unwrap();
+            if let Some(device) = state.devices.iter_mut().find(|d| d.id == id) {
+                device.state = device_state;
+            }
+            if device_state != DeviceState::Active {
+                state.defaults.retain(|_, default_id| default_id != id);
+            }
+        }
+        self.notify(DeviceEvent::StateChanged {
+            id: id.to_string(),
+            state: device_state,
+        });
+    }
+
+    /// Simulates another app or the system mixer changing the volume of `id`.
+    pub fn change_volume_externally(&self, id: &str, scalar: f32, muted: bool) {
+        let volume = {
+            let mut state = self.state.lock().unwrap();
+            let Some(device) = state.devices.iter_mut().find(|d| d.id == id) else {
+                return;
+            };
+            device.volume = scalar.clamp(0.0, 1.0);
+            device.muted = muted;
+            device.volume_info()
+        };
+        self.notify(DeviceEvent::VolumeChanged {
+            id: id.to_string(),
+            volume,
+        });
+    }
+
+    fn with_device<T>(
+        &self,
+        id: &str,
+        f: impl FnOnce(&mut MemoryDevice) -> T,
+    ) -> Result<T, String> {
+        let mut state = self
+            .state
+            .lock()
+            .map_err(|_| "Failed to lock memory backend".to_string())?;
+        let device = state
+            .devices
+            .iter_mut()
+            .find(|d| d.id == id)
+            .ok_or_else(|| format!("Unknown device: {}", id))?;
+        Ok(f(device))
+    }
+
+    pub fn remove_device(&self, id: &str) {
+        {
+            let mut state = self.state.lock().unwrap();
+            state.devices.retain(|d| d.id != id);
+            state.defaults.retain(|_, default_id| default_id != id);
+        }
+        self.notify(DeviceEvent::DeviceRemoved { id: id.to_string() });
+    }
+
+    fn notify(&self, event: DeviceEvent) {
+        let subscribers = self.subscribers.lock().unwrap();
+        for callback in subscribers.iter() {
+            callback(event.clone());
+        }
+    }
+}
+
+#[cfg(test)]
+impl MemoryBackend {
+    /// Speakers "a", "b" and "c" and a microphone "mic", the shared fixture of
+    /// the audio tests. "a" and "mic" are the defaults.
+    pub(crate) fn speakers() -> Self {
+        Self::with_devices([
+            ("a", "Speakers A", DeviceDirection::Render),
+            ("b", "Speakers B", DeviceDirection::Render),
+            ("c", "Speakers C", DeviceDirection::Render),
+            ("mic", "Microphone", DeviceDirection::Capture),
+        ])
+    }
+}
+
+impl AudioBackend for MemoryBackend {
+    fn name(&self) -> &'static str {
+        "memory"
+    }
+
+    fn enumerate_devices(&self, direction: DeviceDirection) -> Result<Vec<AudioDevice>, String> {
+        let state = self
+            .state
+            .lock()
+            .map_err(|_| "Failed to lock memory backend".to_string())?;
+
+        Ok(state
+            .devices
+            .iter()
+            .filter(|d| d.direction == direction)
+            .map(|d| {
+                let default_roles: Vec<DeviceRole> = DeviceRole::ALL
+                    .into_iter()
+                    .filter(|role| state.defaults.get(&(direction, *role)) == Some(&d.id))
+                    .collect();
+                AudioDevice {
+                    id: d.id.clone(),
+                    name: d.name.clone(),
+                    direction: d.direction,
+                    state: d.state,
+                    is_default: default_roles.contains(&DeviceRole::Multimedia),
+                    default_roles,
+                    metadata: DeviceMetadata::default(),
+                }
+            })
+            .collect())
+    }
+
+    fn default_device(
+        &self,
+        direction: DeviceDirection,
+        role: DeviceRole,
+    ) -> Result<Option<String>, String> {
+        let state = self
+            .state
+            .lock()
+            .map_err(|_| "Failed to lock memory backend".to_string())?;
+        Ok(state.defaults.get(&(direction, role)).cloned())
+    }
+
+    fn set_default_device(&self, id: &str, roles: &[DeviceRole]) -> Result<(), String> {
+        let direction = {
+            let mut state = self
+                .state
+                .lock()
+                .map_err(|_| "Failed to lock memory backend".to_string())?;
+            let device = state
+                .devices
+                .iter()
+                .find(|d| d.id == id)
+                .ok_or_else(|| format!("Unknown device: {}", id))?;
+            if device.state != DeviceState::Active {
+                return Err(format!("Device is not active: {}", id));
+            }
+            let direction = device.direction;
+            for role in roles {
+                state.defaults.insert((direction, *role), id.to_string());
+            }
+            direction
+        };
+
+        for role in roles {
+            self.notify(DeviceEvent::DefaultChanged {
+                id: id.to_string(),
+                direction,
+                role: *role,
+            });
+        }
+        Ok(())
+    }
+
+    fn volume(&self, id: &str) -> Result<VolumeInfo, String> {
+        self.with_device(id, |device| device.volume_info())
+    }
+
+    fn set_volume(&self, id: &str, level: VolumeLevel) -> Result<(), String> {
+        self.with_device(id, |device| {
+            device.volume = match level {
+                VolumeLevel::Scalar(scalar) => scalar,
+                VolumeLevel::Db(db) => (db - MIN_DB) / (MAX_DB - MIN_DB),
+            }
+            .clamp(0.0, 1.0);
+        })
+    }
+
+    fn set_mute(&self, id: &str, muted: bool) -> Result<(), String> {
+        self.with_device(id, |device| device.muted = muted)
+    }
+
+    fn set_endpoint_visibility(&self, id: &str, visible: bool) -> Result<(), String> {
+        self.with_device(id, |_| ())?;
+        self.set_device_state(
+            id,
+            if visible {
+                DeviceState::Active
+            } else {
+                DeviceState::Disabled
+            },
+        );
+        Ok(())
+    }
+
+    fn subscribe(&self, callback: EventCallback) {
+        self.subscribers.lock().unwrap().push(callback);
+    }
+}
@@ -1,108 +1,258 @@
+use std::sync::Arc;
 use tauri::{Emitter, Manager};
 
+pub mod backend;
+pub mod cycle;
+#[cfg(windows)]
 pub mod device;
+#[cfg(windows)]
+pub mod format;
+pub mod memory;
+#[cfg(windows)]
+pub mod notification;
+#[cfg(windows)]
 pub mod policy;
+#[cfg(windows)]
+pub mod property;
+#[cfg(target_os = "linux")]
+pub mod pulse;
+pub mod switch;
+pub mod tracker;
+#[cfg(windows)]
+pub mod volume;
+#[cfg(windows)]
+pub mod wasapi;
+#[cfg(windows)]
+pub mod worker;
 
-pub fn cycle_next_device(app_handle: &tauri::AppHandle) -> Result<String, String> {
-    let devices = device::enumerate_devices()?;
-    if devices.is_empty() {
-        return Err("No audio devices found".to_string());
+pub use backend::{
+    AudioBackend, AudioDevice, AudioFormat, DeviceDirection, DeviceEvent, DeviceFormats,
+    DeviceRole, EndpointProperties, ProcessingPeriod, VolumeInfo, VolumeLevel,
+};
+pub use switch::SwitchError;
+pub use tracker::{ChangeReason, DefaultTracker, DeviceChanged};
+
+/// The backend chosen at startup, kept in Tauri state.
+pub type SharedBackend = Arc<dyn AudioBackend>;
+
+/// Picks the backend for this platform. `SOUND_SWITCH_BACKEND=memory` forces the
+/// in-memory backend, e.g. to exercise the switching logic on CI machines.
+pub fn create_backend() -> SharedBackend {
+    match std::env::var("SOUND_SWITCH_BACKEND").as_deref() {
+        Ok("memory") => Arc::new(memory::MemoryBackend::with_devices([
+            ("memory-speakers", "Speakers", DeviceDirection::Render),
+            ("memory-headphones", "Headphones", DeviceDirection::Render),
+            ("memory-microphone", "Microphone", DeviceDirection::Capture),
+        ])),
+        _ => platform_backend(),
     }
+}
+
+#[cfg(windows)]
+fn platform_backend() -> SharedBackend {
+    Arc::new(wasapi::WasapiBackend::new())
+}
+
+#[cfg(target_os = "linux")]
+fn platform_backend() -> SharedBackend {
+    Arc::new(pulse::PulseBackend::new())
+}
+
+#[cfg(not(any(windows, target_os = "linux")))]
+fn platform_backend() -> SharedBackend {
+    Arc::new(memory::MemoryBackend::new())
+}
+
+pub fn backend(app_handle: &tauri::AppHandle) -> SharedBackend {
+    app_handle.state::<SharedBackend>().inner().clone()
+}
+
+pub fn cycle_next_device(
+    app_handle: &tauri::AppHandle,
+    direction: DeviceDirection,
+) -> Result<String, String> {
+    let config = load_config(app_handle);
 
-    // Load config
-    let state = app_handle.state::<std::sync::Mutex<crate::config::ConfigManager>>();
-    let manager = state
-        .lock()
-        .map_err(|_| "Failed to lock config manager".to_string())?;
-    // Reload config to ensure we have latest exclude list
-    let config = manager.load();
-
-    let included_devices: Vec<&device::AudioDevice> = devices
-        .iter()
-        .filter(|d| !config.excluded_device_ids.contains(&d.id))
-        .collect();
-
-    if included_devices.is_empty() {
-        // Fallback to all devices if filtering removed everything or allowed nothing
-        return cycle_next_device_internal(&devices);
+    let outcome = cycle::next_device(
+        backend(app_handle).as_ref(),
+        direction,
+        &config.cycle_roles,
+        config.excluded_ids(direction),
+    )
+    .inspect_err(|e| {
+        // No device to switch to is a failed switch too
+        let _ = app_handle.emit("switch-failed", SwitchError::from(e.clone()));
+    })?;
+    let roles = cycle::effective_roles(&config.cycle_roles).to_vec();
+    if let Err(e) = switch_device(app_handle, &config, &outcome.device, &roles) {
+        // Keep the tray on whatever is actually the default
+        let _ = update_tray_icon(app_handle);
+        return Err(e.into());
     }
 
-    let current_index = included_devices.iter().position(|d| d.is_default);
+    if let Some(display_number) = outcome.display_number {
+        // The tray number tracks the output device
+        if direction == DeviceDirection::Render {
+            set_tray_number(app_handle, display_number);
+        }
 
-    let next_device = match current_index {
-        Some(idx) => included_devices[(idx + 1) % included_devices.len()],
-        None => included_devices[0],
-    };
+        // Emit event to update frontend
+        let _ = app_handle.emit(
+            "device-changed",
+            DeviceChanged {
+                reason: ChangeReason::Cycle,
+                direction,
+                id: outcome.device.id.clone(),
+            },
+        );
+    }
 
-    policy::set_default_device(&next_device.id)?;
+    Ok(outcome.device.name)
+}
 
-    // Update Tray Icon
-    // Calculate 1-based index for the NEXT device in the included list
-    let next_index_in_list = match current_index {
-        Some(idx) => (idx + 1) % included_devices.len(),
-        None => 0,
-    };
-    // Index + 1 for display
-    let display_number = next_index_in_list + 1;
+/// Runs `cycle_next_device` off the calling thread, for the hotkey and tray
+/// handlers that must not wait on the audio worker. Failures are reported
+/// through the `switch-failed` event.
+pub fn cycle_in_background(app_handle: &tauri::AppHandle, direction: DeviceDirection) {
+    // Quick presses queue up instead of both cycling from the same default
+    static CYCLING: std::sync::Mutex<()> = std::sync::Mutex::new(());
 
-    if let Some(tray) = app_handle.tray_by_id("tray") {
-        if let Some(icon) = crate::icon_gen::generate_number_icon(display_number) {
-            let _ = tray.set_icon(Some(icon));
+    let app_handle = app_handle.clone();
+    tauri::async_runtime::spawn_blocking(move || {
+        let _guard = CYCLING.lock();
+        let _ = cycle_next_device(&app_handle, direction);
+    });
+}
+
+/// Makes the device `id` the default for `roles`.
+pub fn set_active_device(
+    app_handle: &tauri::AppHandle,
+    id: &str,
+    roles: &[DeviceRole],
+) -> Result<(), SwitchError> {
+    let backend = backend(app_handle);
+    let mut device = None;
+    for direction in DeviceDirection::ALL {
+        device = backend
+            .enumerate_devices(direction)?
+            .into_iter()
+            .find(|d| d.id == id);
+        if device.is_some() {
+            break;
         }
     }
+    let device = device.ok_or_else(|| format!("Unknown device: {}", id))?;
 
-    // Emit event to update frontend
-    let _ = app_handle.emit("device-changed", ());
-    Ok(next_device.name.clone())
+    let config = load_config(app_handle);
+    let result = switch_device(app_handle, &config, &device, roles);
+    let _ = update_tray_icon(app_handle);
+    result
 }
 
-fn cycle_next_device_internal(devices: &[device::AudioDevice]) -> Result<String, String> {
-    let current_index = devices.iter().position(|d| d.is_default);
+/// Switches through `switch::switch_default`, reports an enforced volume cap and
+/// stores the volume it remembered. A failed switch is also emitted as
+/// `switch-failed`, since hotkey and tray switches have nobody to return to.
+fn switch_device(
+    app_handle: &tauri::AppHandle,
+    config: &crate::config::Config,
+    device: &AudioDevice,
+    roles: &[DeviceRole],
+) -> Result<(), SwitchError> {
+    let outcome = switch::switch_default(
+        backend(app_handle).as_ref(),
+        app_handle.state::<DefaultTracker>().inner(),
+        config,
+        device,
+        roles,
+    )
+    .inspect_err(|e| {
+        let _ = app_handle.emit("switch-failed", e);
+    })?;
 
-    let next_index = match current_index {
-        Some(idx) => (idx + 1) % devices.len(),
-        None => 0,
-    };
+    if let Some(capped) = outcome.capped {
+        let _ = app_handle.emit("volume-capped", capped);
+    }
+    // Only the remembered volume is written back, into the config as it is
+    // now: settings saved while the switch was verified must not be lost
+    if let Some((id, volume)) = outcome.remembered {
+        crate::config::remember_volume(app_handle, &id, volume);
+    }
+    Ok(())
+}
+
+/// Called for every backend event: keeps the tray number current and forwards
+/// the event to the frontend as `audio-device-event`. A default change nobody
+/// here asked for is also announced as `device-changed` with reason `external`.
+pub fn handle_device_event(app_handle: &tauri::AppHandle, event: DeviceEvent) {
+    if let DeviceEvent::DefaultChanged {
+        id,
+        direction,
+        role,
+    } = &event
+    {
+        let external = app_handle
+            .state::<DefaultTracker>()
+            .observe(*direction, *role, id);
+        let primary_role = app_handle
+            .state::<crate::config::AppState>()
+            .read(|config| cycle::primary_role(&config.cycle_roles));
+        if external && *role == primary_role {
+            let _ = app_handle.emit(
+                "device-changed",
+                DeviceChanged {
+                    reason: ChangeReason::External,
+                    direction: *direction,
+                    id: id.clone(),
+                },
+            );
+        }
+    }
 
-    let next_device = &devices[next_index];
-    policy::set_default_device(&next_device.id)?;
+    // Something else raised the volume; pull it back under the cap
+    if let DeviceEvent::VolumeChanged { id, .. } = &event {
+        let config = load_config(app_handle);
+        if let Ok(Some(capped)) =
+            switch::enforce_max_volume(backend(app_handle).as_ref(), &config, id)
+        {
+            let _ = app_handle.emit("volume-capped", capped);
+        }
+    }
+
+    // The tray shows the default device, which a volume change leaves alone;
+    // volume events come in bursts while a slider is dragged
+    if !matches!(event, DeviceEvent::VolumeChanged { .. }) {
+        let _ = update_tray_icon(app_handle);
+    }
+    let _ = app_handle.emit("audio-device-event", event);
+}
 
-    Ok(next_device.name.clone())
+fn load_config(app_handle: &tauri::AppHandle) -> crate::config::Config {
+    app_handle.state::<crate::config::AppState>().config()
 }
 
 pub fn update_tray_icon(app_handle: &tauri::AppHandle) -> Result<(), String> {
-    let devices = device::enumerate_devices()?;
+    let devices = backend(app_handle).enumerate_devices(DeviceDirection::Render)?;
     if devices.is_empty() {
         return Ok(());
     }
 
-    // Load config
-    let state = app_handle.state::<std::sync::Mutex<crate::config::ConfigManager>>();
-    let manager = state
-        .lock()
-        .map_err(|_| "Failed to lock config manager".to_string())?;
-    // Reload config to ensure we have latest exclude list
-    let config = manager.load();
-
-    let included_devices: Vec<&device::AudioDevice> = devices
-        .iter()
-        .filter(|d| !config.excluded_device_ids.contains(&d.id))
-        .collect();
-
-    if included_devices.is_empty() {
-        return Ok(());
+    let display_number = app_handle
+        .state::<crate::config::AppState>()
+        .read(|config| {
+            let role = cycle::primary_role(&config.cycle_roles);
+            cycle::display_number(&devices, &config.excluded_device_ids, role)
+        });
+    if let Some(display_number) = display_number {
+        set_tray_number(app_handle, display_number);
     }
 
-    let current_index = included_devices.iter().position(|d| d.is_default);
+    Ok(())
+}
 
-    if let Some(idx) = current_index {
-        let display_number = idx + 1;
-        if let Some(tray) = app_handle.tray_by_id("tray") {
-            if let Some(icon) = crate::icon_gen::generate_number_icon(display_number) {
-                let _ = tray.set_icon(Some(icon));
-            }
+fn set_tray_number(app_handle: &tauri::AppHandle, display_number: usize) {
+    if let Some(tray) = app_handle.tray_by_id("tray") {
+        if let Some(icon) = crate::icon_gen::generate_number_icon(display_number) {
+            let _ = tray.set_icon(Some(icon));
         }
     }
-
-    Ok(())
 }
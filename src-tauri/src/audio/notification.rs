@@ -0,0 +1,255 @@
+use super::backend::{DeviceDirection, DeviceEvent, DeviceRole};
+use super::device::{self, to_device_state};
+use super::volume::{self, VolumeListener};
+use super::worker::AudioWorker;
+use std::collections::HashMap;
+use std::sync::mpsc::{self, Sender};
+use std::sync::{Arc, Mutex};
+use windows::core::{implement, PCWSTR};
+use windows::Win32::Media::Audio::{
+    eCapture, eCommunications, eConsole, eMultimedia, eRender, EDataFlow, ERole,
+    IMMDeviceEnumerator, IMMNotificationClient, IMMNotificationClient_Impl, MMDeviceEnumerator,
+    DEVICE_STATE,
+};
+use windows::Win32::System::Com::{
+    CoCreateInstance, CoInitializeEx, CLSCTX_ALL, COINIT_MULTITHREADED,
+};
+use windows::Win32::UI::Shell::PropertiesSystem::PROPERTYKEY;
+
+/// Keeps an endpoint notification client, and a volume listener on every
+/// active endpoint, registered for as long as it lives.
+///
+/// All of them belong to the watcher's own dispatcher thread, which sets up
+/// COM for itself, registers them and unregisters them again when stopped.
+/// MMDevice callbacks must not block or call back into the enumerator, so the
+/// clients only queue notifications for that thread to hand on. Devices and
+/// volumes are read through the audio worker, like every other request.
+pub struct EndpointWatcher {
+    sender: Sender<Notification>,
+}
+
+enum Notification {
+    Device(DeviceEvent),
+    /// The volume of this endpoint was changed externally.
+    Volume(String),
+    Stop,
+}
+
+impl EndpointWatcher {
+    /// Starts the dispatcher thread and waits until the notification client is
+    /// registered on it.
+    pub fn start<F>(worker: Arc<AudioWorker>, dispatch: F) -> Result<Self, String>
+    where
+        F: Fn(DeviceEvent) + Send + 'static,
+    {
+        let (sender, receiver) = mpsc::channel();
+        let (started, start_result) = mpsc::channel();
+        let thread_sender = sender.clone();
+        std::thread::spawn(move || {
+            // Callbacks arrive on threads of the audio service; the
+            // multithreaded apartment takes them without a message loop
+            let _ = unsafe { CoInitializeEx(None, COINIT_MULTITHREADED) };
+
+            let registration = match Registration::register(thread_sender.clone()) {
+                Ok(registration) => registration,
+                Err(e) => {
+                    let _ = started.send(Err(e));
+                    return;
+                }
+            };
+            let _ = started.send(Ok(()));
+
+            let mut listeners = HashMap::new();
+            refresh_volume_listeners(&worker, &mut listeners, &thread_sender);
+            for notification in receiver {
+                match notification {
+                    Notification::Device(event) => {
+                        if !matches!(event, DeviceEvent::DefaultChanged { .. }) {
+                            refresh_volume_listeners(&worker, &mut listeners, &thread_sender);
+                        }
+                        dispatch(event);
+                    }
+                    Notification::Volume(id) => {
+                        let device_id = id.clone();
+                        if let Ok(volume) = worker.run(move || volume::get_volume(&device_id)) {
+                            dispatch(DeviceEvent::VolumeChanged { id, volume });
+                        }
+                    }
+                    Notification::Stop => break,
+                }
+            }
+
+            drop(listeners);
+            registration.unregister();
+        });
+
+        start_result
+            .recv()
+            .map_err(|_| "The endpoint watcher failed to start".to_string())??;
+        Ok(Self { sender })
+    }
+}
+
+impl Drop for EndpointWatcher {
+    fn drop(&mut self) {
+        let _ = self.sender.send(Notification::Stop);
+    }
+}
+
+/// The notification client, registered with an enumerator of the dispatcher
+/// thread.
+struct Registration {
+    enumerator: IMMDeviceEnumerator,
+    client: IMMNotificationClient,
+}
+
+impl Registration {
+    fn register(sender: Sender<Notification>) -> Result<Self, String> {
+        unsafe {
+            let enumerator: IMMDeviceEnumerator =
+                CoCreateInstance(&MMDeviceEnumerator, None, CLSCTX_ALL)
+                    .map_err(|e| format!("Failed to create device enumerator: {}", e))?;
+
+            let client: IMMNotificationClient = NotificationClient {
+                sender: Mutex::new(sender),
+            }
+            .into();
+            enumerator
+                .RegisterEndpointNotificationCallback(&client)
+                .map_err(|e| format!("Failed to register endpoint notifications: {}", e))?;
+
+            Ok(Self { enumerator, client })
+        }
+    }
+
+    fn unregister(self) {
+        unsafe {
+            let _ = self
+                .enumerator
+                .UnregisterEndpointNotificationCallback(&self.client);
+        }
+    }
+}
+
+/// Listens to the volume of every active endpoint, dropping listeners of
+/// endpoints that went away. The listeners stay on the dispatcher thread,
+/// which owns their callbacks.
+fn refresh_volume_listeners(
+    worker: &AudioWorker,
+    listeners: &mut HashMap<String, VolumeListener>,
+    sender: &Sender<Notification>,
+) {
+    let mut active_ids = Vec::new();
+    for direction in DeviceDirection::ALL {
+        if let Ok(devices) = worker.run(move || device::enumerate_devices(direction)) {
+            active_ids.extend(devices.into_iter().filter(|d| d.is_active()).map(|d| d.id));
+        }
+    }
+
+    listeners.retain(|id, _| active_ids.contains(id));
+    for id in active_ids {
+        if listeners.contains_key(&id) {
+            continue;
+        }
+        let sender = sender.clone();
+        let device_id = id.clone();
+        let listener = VolumeListener::register(&id, move || {
+            let _ = sender.send(Notification::Volume(device_id.clone()));
+        });
+        if let Ok(listener) = listener {
+            listeners.insert(id, listener);
+        }
+    }
+}
+
+#[implement(IMMNotificationClient)]
+struct NotificationClient {
+    sender: Mutex<Sender<Notification>>,
+}
+
+impl NotificationClient {
+    fn send(&self, event: DeviceEvent) {
+        if let Ok(sender) = self.sender.lock() {
+            let _ = sender.send(Notification::Device(event));
+        }
+    }
+}
+
+impl IMMNotificationClient_Impl for NotificationClient_Impl {
+    fn OnDeviceStateChanged(
+        &self,
+        pwstrdeviceid: &PCWSTR,
+        dwnewstate: DEVICE_STATE,
+    ) -> windows::core::Result<()> {
+        if let Some(id) = to_string(pwstrdeviceid) {
+            self.send(DeviceEvent::StateChanged {
+                id,
+                state: to_device_state(dwnewstate),
+            });
+        }
+        Ok(())
+    }
+
+    fn OnDeviceAdded(&self, pwstrdeviceid: &PCWSTR) -> windows::core::Result<()> {
+        if let Some(id) = to_string(pwstrdeviceid) {
+            self.send(DeviceEvent::DeviceAdded { id });
+        }
+        Ok(())
+    }
+
+    fn OnDeviceRemoved(&self, pwstrdeviceid: &PCWSTR) -> windows::core::Result<()> {
+        if let Some(id) = to_string(pwstrdeviceid) {
+            self.send(DeviceEvent::DeviceRemoved { id });
+        }
+        Ok(())
+    }
+
+    fn OnDefaultDeviceChanged(
+        &self,
+        flow: EDataFlow,
+        role: ERole,
+        pwstrdefaultdeviceid: &PCWSTR,
+    ) -> windows::core::Result<()> {
+        // A null ID means the last device of that flow went away; the state
+        // change notification already covers that
+        let direction = if flow == eRender {
+            DeviceDirection::Render
+        } else if flow == eCapture {
+            DeviceDirection::Capture
+        } else {
+            return Ok(());
+        };
+        let role = if role == eConsole {
+            DeviceRole::Console
+        } else if role == eMultimedia {
+            DeviceRole::Multimedia
+        } else if role == eCommunications {
+            DeviceRole::Communications
+        } else {
+            return Ok(());
+        };
+        if let Some(id) = to_string(pwstrdefaultdeviceid) {
+            self.send(DeviceEvent::DefaultChanged {
+                id,
+                direction,
+                role,
+            });
+        }
+        Ok(())
+    }
+
+    fn OnPropertyValueChanged(
+        &self,
+        _pwstrdeviceid: &PCWSTR,
+        _key: &PROPERTYKEY,
+    ) -> windows::core::Result<()> {
+        Ok(())
+    }
+}
+
+fn to_string(value: &PCWSTR) -> Option<String> {
+    if value.is_null() {
+        return None;
+    }
+    unsafe { value.to_string().ok() }
+}
@@ -1,51 +1,65 @@
 #![allow(non_camel_case_types)]
+use super::backend::ProcessingPeriod;
+use std::cell::RefCell;
 use windows::core::{interface, HRESULT, PCWSTR};
 use windows::Win32::Media::Audio::{eCommunications, eConsole, eMultimedia, ERole};
-use windows::Win32::System::Com::{CoCreateInstance, CoInitializeEx, CLSCTX_ALL};
+use windows::Win32::System::Com::{CoCreateInstance, CLSCTX_ALL};
 
 // Undocumented IPolicyConfig interface
 // GUID for IPolicyConfig interface
 #[interface("f8679f50-850a-41cf-9c72-430f290290c8")]
-unsafe trait IPolicyConfig: windows::core::IUnknown {
-    fn get_mix_format(&self, pcwstr: PCWSTR, waveformat: *mut *mut core::ffi::c_void) -> HRESULT;
-    fn get_device_format(
+pub unsafe trait IPolicyConfig: windows::core::IUnknown {
+    pub fn get_mix_format(
+        &self,
+        pcwstr: PCWSTR,
+        waveformat: *mut *mut core::ffi::c_void,
+    ) -> HRESULT;
+    pub fn get_device_format(
         &self,
         pcwstr: PCWSTR,
         default: i32,
         waveformat: *mut *mut core::ffi::c_void,
     ) -> HRESULT;
-    fn reset_device_format(&self, pcwstr: PCWSTR) -> HRESULT;
-    fn set_device_format(
+    pub fn reset_device_format(&self, pcwstr: PCWSTR) -> HRESULT;
+    pub fn set_device_format(
         &self,
         pcwstr: PCWSTR,
         waveformat: *mut core::ffi::c_void,
         waveformat2: *mut core::ffi::c_void,
     ) -> HRESULT;
-    fn get_processing_period(
+    pub fn get_processing_period(
         &self,
         pcwstr: PCWSTR,
         input: i32,
         period: *mut i64,
         period2: *mut i64,
     ) -> HRESULT;
-    fn set_processing_period(&self, pcwstr: PCWSTR, period: *mut i64, period2: *mut i64)
-        -> HRESULT;
-    fn get_share_mode(&self, pcwstr: PCWSTR, mode: *mut i32) -> HRESULT;
-    fn set_share_mode(&self, pcwstr: PCWSTR, mode: *mut i32) -> HRESULT;
-    fn get_property_value(
+    pub fn set_processing_period(
+        &self,
+        pcwstr: PCWSTR,
+        period: *mut i64,
+        period2: *mut i64,
+    ) -> HRESULT;
+    // Not implemented on current Windows builds, only kept for the vtable
+    // layout; exclusive mode is read and written as an endpoint property
+    pub fn get_share_mode(&self, pcwstr: PCWSTR, mode: *mut i32) -> HRESULT;
+    pub fn set_share_mode(&self, pcwstr: PCWSTR, mode: *mut i32) -> HRESULT;
+    pub fn get_property_value(
         &self,
         pcwstr: PCWSTR,
+        fx_store: i32,
         key: *const core::ffi::c_void,
         value: *mut core::ffi::c_void,
     ) -> HRESULT;
-    fn set_property_value(
+    pub fn set_property_value(
         &self,
         pcwstr: PCWSTR,
+        fx_store: i32,
         key: *const core::ffi::c_void,
         value: *const core::ffi::c_void,
     ) -> HRESULT;
-    fn set_default_endpoint(&self, pcwstr: PCWSTR, role: ERole) -> HRESULT;
-    fn set_endpoint_visibility(&self, pcwstr: PCWSTR, visibility: i32) -> HRESULT;
+    pub fn set_default_endpoint(&self, pcwstr: PCWSTR, role: ERole) -> HRESULT;
+    pub fn set_endpoint_visibility(&self, pcwstr: PCWSTR, visibility: i32) -> HRESULT;
 }
 
 // Class ID for PolicyConfigClient
@@ -53,37 +67,116 @@ unsafe trait IPolicyConfig: windows::core::IUnknown {
 const CLSID_POLICY_CONFIG: windows::core::GUID =
     windows::core::GUID::from_u128(0x870af99c_171d_4f9e_af0d_e63df40c2bc9);
 
+thread_local! {
+    static POLICY_CONFIG: RefCell<Option<IPolicyConfig>> = const { RefCell::new(None) };
+}
+
+/// The IPolicyConfig object of the calling thread, created on first use. It
+/// needs a single-threaded apartment, which the audio worker provides.
+pub fn policy_config() -> Result<IPolicyConfig, String> {
+    POLICY_CONFIG.with(|cached| {
+        if let Some(policy_config) = cached.borrow().as_ref() {
+            return Ok(policy_config.clone());
+        }
+        let policy_config: IPolicyConfig =
+            unsafe { CoCreateInstance(&CLSID_POLICY_CONFIG, None, CLSCTX_ALL) }
+                .map_err(|e| format!("Failed to create IPolicyConfig: {}", e))?;
+        *cached.borrow_mut() = Some(policy_config.clone());
+        Ok(policy_config)
+    })
+}
+
+/// Null-terminated UTF-16 copy of a device ID, to be passed as a `PCWSTR`.
+pub fn to_wide(device_id: &str) -> Vec<u16> {
+    device_id.encode_utf16().chain(std::iter::once(0)).collect()
+}
+
 pub fn set_default_device(device_id: &str) -> Result<(), String> {
+    // Console (Standard), Multimedia, and Communications (usually expected for "Default Device")
+    set_default_device_for_roles(device_id, &[eConsole, eMultimedia, eCommunications])
+}
+
+pub fn set_default_device_for_roles(device_id: &str, roles: &[ERole]) -> Result<(), String> {
+    let policy_config = policy_config()?;
+    let id_wide = to_wide(device_id);
+    let id_pcwstr = PCWSTR(id_wide.as_ptr());
+
     unsafe {
-        // Ensure COM is initialized
-        // Use COINIT_APARTMENTTHREADED (STA) as strictly required by some Shell/UI COM objects.
-        let _ = CoInitializeEx(None, windows::Win32::System::Com::COINIT_APARTMENTTHREADED);
+        for &role in roles {
+            policy_config
+                .set_default_endpoint(id_pcwstr, role)
+                .ok()
+                .map_err(|e| format!("Failed to set {} default: {}", role_name(role), e))?;
+        }
 
-        let policy_config: IPolicyConfig = CoCreateInstance(&CLSID_POLICY_CONFIG, None, CLSCTX_ALL)
-            .map_err(|e| format!("Failed to create IPolicyConfig: {}", e))?;
+        Ok(())
+    }
+}
+
+/// Periods are passed around in 100-nanosecond units (REFERENCE_TIME).
+const PERIOD_UNITS_PER_MS: f32 = 10_000.0;
 
-        let mut id_wide: Vec<u16> = device_id.encode_utf16().collect();
-        id_wide.push(0);
-        let id_pcwstr = PCWSTR(id_wide.as_ptr());
+pub fn get_processing_period(device_id: &str) -> Result<ProcessingPeriod, String> {
+    let policy_config = policy_config()?;
+    let id_wide = to_wide(device_id);
+    let id_pcwstr = PCWSTR(id_wide.as_ptr());
 
-        // Set for Console (Standard)
+    // With `input` set the defaults come back, otherwise the periods in effect
+    let (mut current, mut default, mut minimum, mut ignored) = (0i64, 0i64, 0i64, 0i64);
+    unsafe {
         policy_config
-            .set_default_endpoint(id_pcwstr, eConsole)
+            .get_processing_period(id_pcwstr, 0, &mut current, &mut ignored)
             .ok()
-            .map_err(|e| format!("Failed to set Console default: {}", e))?;
+            .map_err(|e| format!("Failed to get processing period: {}", e))?;
+        policy_config
+            .get_processing_period(id_pcwstr, 1, &mut default, &mut minimum)
+            .ok()
+            .map_err(|e| format!("Failed to get processing period: {}", e))?;
+    }
+
+    Ok(ProcessingPeriod {
+        current: current as f32 / PERIOD_UNITS_PER_MS,
+        default: default as f32 / PERIOD_UNITS_PER_MS,
+        minimum: minimum as f32 / PERIOD_UNITS_PER_MS,
+    })
+}
 
-        // Set for Multimedia
+pub fn set_processing_period(device_id: &str, period: f32) -> Result<(), String> {
+    let periods = get_processing_period(device_id)?;
+    if !(periods.minimum..=periods.default).contains(&period) {
+        return Err(format!(
+            "Processing period must be between {} and {} ms",
+            periods.minimum, periods.default
+        ));
+    }
+
+    let policy_config = policy_config()?;
+    let id_wide = to_wide(device_id);
+    let mut period = (period * PERIOD_UNITS_PER_MS).round() as i64;
+    unsafe {
         policy_config
-            .set_default_endpoint(id_pcwstr, eMultimedia)
+            .set_processing_period(PCWSTR(id_wide.as_ptr()), &mut period, std::ptr::null_mut())
             .ok()
-            .map_err(|e| format!("Failed to set Multimedia default: {}", e))?;
+            .map_err(|e| format!("Failed to set processing period: {}", e))
+    }
+}
 
-        // Set for Communications (Optional, but usually expected for "Default Device")
+pub fn set_endpoint_visibility(device_id: &str, visible: bool) -> Result<(), String> {
+    let policy_config = policy_config()?;
+    let id_wide = to_wide(device_id);
+    unsafe {
         policy_config
-            .set_default_endpoint(id_pcwstr, eCommunications)
+            .set_endpoint_visibility(PCWSTR(id_wide.as_ptr()), visible as i32)
             .ok()
-            .map_err(|e| format!("Failed to set Communications default: {}", e))?;
+            .map_err(|e| format!("Failed to set endpoint visibility: {}", e))
+    }
+}
 
-        Ok(())
+fn role_name(role: ERole) -> &'static str {
+    match role {
+        r if r == eConsole => "Console",
+        r if r == eMultimedia => "Multimedia",
+        r if r == eCommunications => "Communications",
+        _ => "Unknown",
     }
 }
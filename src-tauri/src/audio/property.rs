@@ -0,0 +1,181 @@
+use super::policy::{policy_config, to_wide};
+use core::ffi::c_void;
+use std::marker::PhantomData;
+use windows::core::{BSTR, GUID, PCWSTR, PROPVARIANT};
+use windows::Win32::Devices::FunctionDiscovery::{
+    PKEY_DeviceClass_IconPath, PKEY_DeviceInterface_FriendlyName, PKEY_Device_ContainerId,
+    PKEY_Device_DeviceDesc, PKEY_Device_FriendlyName,
+};
+use windows::Win32::Media::Audio::{
+    IMMDevice, PKEY_AudioEndpoint_Disable_SysFx, PKEY_AudioEndpoint_FormFactor,
+};
+use windows::Win32::System::Com::StructuredStorage::{PropVariantChangeType, PVCHF_DEFAULT};
+use windows::Win32::System::Com::STGM_READ;
+use windows::Win32::System::Variant::VT_LPWSTR;
+use windows::Win32::UI::Shell::PropertiesSystem::{IPropertyStore, PROPERTYKEY};
+
+/// An endpoint property together with the type its value is stored as.
+pub struct EndpointProperty<T> {
+    pub key: PROPERTYKEY,
+    value: PhantomData<T>,
+}
+
+impl<T> EndpointProperty<T> {
+    const fn new(key: PROPERTYKEY) -> Self {
+        Self {
+            key,
+            value: PhantomData,
+        }
+    }
+}
+
+pub const FRIENDLY_NAME: EndpointProperty<String> = EndpointProperty::new(PKEY_Device_FriendlyName);
+pub const DESCRIPTION: EndpointProperty<String> = EndpointProperty::new(PKEY_Device_DeviceDesc);
+pub const INTERFACE_NAME: EndpointProperty<String> =
+    EndpointProperty::new(PKEY_DeviceInterface_FriendlyName);
+pub const FORM_FACTOR: EndpointProperty<u32> = EndpointProperty::new(PKEY_AudioEndpoint_FormFactor);
+/// Read through PropVariantToBSTR, so the GUID comes back as `{...}`.
+pub const CONTAINER_ID: EndpointProperty<String> = EndpointProperty::new(PKEY_Device_ContainerId);
+pub const ICON_PATH: EndpointProperty<String> = EndpointProperty::new(PKEY_DeviceClass_IconPath);
+
+/// 1 when audio enhancements (system effects) are turned off.
+pub const DISABLE_SYSFX: EndpointProperty<u32> =
+    EndpointProperty::new(PKEY_AudioEndpoint_Disable_SysFx);
+
+// "Listen to this device" on the Listen tab of a recording endpoint
+const LISTEN_FMTID: GUID = GUID::from_u128(0x24dbb0fc_9311_4b3d_9cf0_18ff155639d4);
+pub const LISTEN_ENABLED: EndpointProperty<bool> = EndpointProperty::new(PROPERTYKEY {
+    fmtid: LISTEN_FMTID,
+    pid: 1,
+});
+/// ID of the render endpoint to play through; empty for the default device.
+pub const LISTEN_TARGET: EndpointProperty<String> = EndpointProperty::new(PROPERTYKEY {
+    fmtid: LISTEN_FMTID,
+    pid: 0,
+});
+
+// Exclusive-mode checkboxes on the Advanced tab of an endpoint
+const EXCLUSIVE_FMTID: GUID = GUID::from_u128(0xb3f8fa53_0004_438e_9003_51a46e139bfc);
+/// 1 when applications may take exclusive control of the endpoint. Unset
+/// means allowed, the Windows default.
+pub const ALLOW_EXCLUSIVE: EndpointProperty<u32> = EndpointProperty::new(PROPERTYKEY {
+    fmtid: EXCLUSIVE_FMTID,
+    pid: 3,
+});
+
+/// Types an endpoint property can be read as and written from.
+pub trait PropertyValue: Sized {
+    /// `None` for empty or differently typed values.
+    fn from_variant(value: &PROPVARIANT) -> Option<Self>;
+    fn to_variant(&self) -> Result<PROPVARIANT, String>;
+}
+
+impl PropertyValue for String {
+    /// Reads strings, and anything PropVariantToBSTR can format as one.
+    fn from_variant(value: &PROPVARIANT) -> Option<Self> {
+        let text = BSTR::try_from(value).ok()?.to_string();
+        (!text.is_empty()).then_some(text)
+    }
+
+    fn to_variant(&self) -> Result<PROPVARIANT, String> {
+        // The audio service expects VT_LPWSTR, not the BSTR `From` gives
+        let bstr = PROPVARIANT::from(self.as_str());
+        let mut value = PROPVARIANT::new();
+        unsafe {
+            PropVariantChangeType(&mut value, &bstr, PVCHF_DEFAULT, VT_LPWSTR)
+                .map_err(|e| format!("Failed to convert property value: {}", e))?;
+        }
+        Ok(value)
+    }
+}
+
+impl PropertyValue for u32 {
+    fn from_variant(value: &PROPVARIANT) -> Option<Self> {
+        u32::try_from(value).ok()
+    }
+
+    fn to_variant(&self) -> Result<PROPVARIANT, String> {
+        Ok(PROPVARIANT::from(*self))
+    }
+}
+
+impl PropertyValue for bool {
+    fn from_variant(value: &PROPVARIANT) -> Option<Self> {
+        bool::try_from(value).ok()
+    }
+
+    fn to_variant(&self) -> Result<PROPVARIANT, String> {
+        Ok(PROPVARIANT::from(*self))
+    }
+}
+
+/// Read-only access to an open endpoint's property store. Missing, empty or
+/// differently typed properties read as `None`.
+pub struct PropertyReader {
+    store: IPropertyStore,
+}
+
+impl PropertyReader {
+    pub fn open(device: &IMMDevice) -> windows::core::Result<Self> {
+        let store = unsafe { device.OpenPropertyStore(STGM_READ)? };
+        Ok(Self { store })
+    }
+
+    pub fn get<T: PropertyValue>(&self, property: &EndpointProperty<T>) -> Option<T> {
+        let value = unsafe { self.store.GetValue(&property.key).ok()? };
+        if value.is_empty() {
+            return None;
+        }
+        T::from_variant(&value)
+    }
+}
+
+/// Reads a property of `device_id` through IPolicyConfig, which also sees the
+/// values the audio service keeps for the endpoint.
+pub fn read_property<T: PropertyValue>(
+    device_id: &str,
+    property: &EndpointProperty<T>,
+) -> Result<Option<T>, String> {
+    let policy_config = policy_config()?;
+    let id_wide = to_wide(device_id);
+
+    let mut value = PROPVARIANT::new();
+    unsafe {
+        policy_config
+            .get_property_value(
+                PCWSTR(id_wide.as_ptr()),
+                0,
+                &property.key as *const _ as *const c_void,
+                &mut value as *mut _ as *mut c_void,
+            )
+            .ok()
+            .map_err(|e| format!("Failed to get property: {}", e))?;
+    }
+    if value.is_empty() {
+        return Ok(None);
+    }
+    Ok(T::from_variant(&value))
+}
+
+/// Writes a property of `device_id` through IPolicyConfig. Unlike writing the
+/// property store directly, this doesn't need elevation.
+pub fn write_property<T: PropertyValue>(
+    device_id: &str,
+    property: &EndpointProperty<T>,
+    value: &T,
+) -> Result<(), String> {
+    let value = value.to_variant()?;
+    let policy_config = policy_config()?;
+    let id_wide = to_wide(device_id);
+    unsafe {
+        policy_config
+            .set_property_value(
+                PCWSTR(id_wide.as_ptr()),
+                0,
+                &property.key as *const _ as *const c_void,
+                &value as *const _ as *const c_void,
+            )
+            .ok()
+            .map_err(|e| format!("Failed to set property: {}", e))
+    }
+}
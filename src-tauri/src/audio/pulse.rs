@@ -0,0 +1,558 @@
+use super::backend::{
+    AudioBackend, AudioDevice, DeviceDirection, DeviceEvent, DeviceFormFactor, DeviceMetadata,
+    DeviceRole, DeviceState, EventCallback, VolumeInfo, VolumeLevel,
+};
+use libpulse_binding as pulse;
+use pulse::callbacks::ListResult;
+use pulse::context::subscribe::{Facility, InterestMaskSet};
+use pulse::context::{Context, FlagSet as ContextFlagSet, State as ContextState};
+use pulse::def::PortAvailable;
+use pulse::mainloop::standard::{IterateResult, Mainloop};
+use pulse::operation::{Operation, State as OperationState};
+use pulse::proplist::{properties, Proplist};
+use pulse::volume::{ChannelVolumes, Volume, VolumeDB};
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::rc::Rc;
+use std::sync::{Arc, Mutex, Once};
+use std::time::{Duration, Instant};
+
+const APP_NAME: &str = "SoundSwitch";
+
+/// Silence is -inf dB, which doesn't survive JSON; report this instead.
+const MIN_DB: f64 = -120.0;
+
+/// Pauses before reconnecting the listener after it lost the server, doubling
+/// up to the maximum while the server stays away.
+const MIN_RECONNECT_DELAY: Duration = Duration::from_millis(500);
+const MAX_RECONNECT_DELAY: Duration = Duration::from_secs(30);
+
+type KnownVolumes = Arc<Mutex<HashMap<String, VolumeInfo>>>;
+
+/// PulseAudio backend, which also covers PipeWire through `pipewire-pulse`.
+/// Render devices are sinks and capture devices are sources (minus sink
+/// monitors), identified by name. PulseAudio has a single default sink and
+/// source, so every role maps onto them.
+pub struct PulseBackend {
+    subscribers: Arc<Mutex<Vec<EventCallback>>>,
+    listener: Once,
+    /// Last volume seen or set per device, so the listener only reports
+    /// changes made by someone else.
+    volumes: KnownVolumes,
+}
+
+impl Default for PulseBackend {
+    fn default() -> Self {
+        Self {
+            subscribers: Default::default(),
+            listener: Once::new(),
+            volumes: Default::default(),
+        }
+    }
+}
+
+impl PulseBackend {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn expect_volume(&self, id: &str, volume: VolumeInfo) {
+        if let Ok(mut volumes) = self.volumes.lock() {
+            volumes.insert(id.to_string(), volume);
+        }
+    }
+}
+
+impl AudioBackend for PulseBackend {
+    fn name(&self) -> &'static str {
+        "pulse"
+    }
+
+    fn enumerate_devices(&self, direction: DeviceDirection) -> Result<Vec<AudioDevice>, String> {
+        let mut connection = Connection::open()?;
+        let default_name = connection.default_name(direction)?;
+
+        Ok(connection
+            .devices(direction)?
+            .into_iter()
+            .map(|device| {
+                let is_default = default_name.as_deref() == Some(device.id.as_str());
+                AudioDevice {
+                    is_default,
+                    default_roles: if is_default {
+                        DeviceRole::ALL.to_vec()
+                    } else {
+                        Vec::new()
+                    },
+                    id: device.id,
+                    name: device.name,
+                    direction,
+                    state: device.state,
+                    metadata: device.metadata,
+                }
+            })
+            .collect())
+    }
+
+    fn default_device(
+        &self,
+        direction: DeviceDirection,
+        _role: DeviceRole,
+    ) -> Result<Option<String>, String> {
+        Connection::open()?.default_name(direction)
+    }
+
+    fn set_default_device(&self, id: &str, roles: &[DeviceRole]) -> Result<(), String> {
+        if roles.is_empty() {
+            return Ok(());
+        }
+
+        let mut connection = Connection::open()?;
+        let (direction, _) = connection.device(id)?;
+        connection.set_default(direction, id)
+    }
+
+    fn volume(&self, id: &str) -> Result<VolumeInfo, String> {
+        let (_, device) = Connection::open()?.device(id)?;
+        Ok(to_volume_info(&device.volume, device.muted))
+    }
+
+    fn set_volume(&self, id: &str, level: VolumeLevel) -> Result<(), String> {
+        let mut connection = Connection::open()?;
+        let (direction, device) = connection.device(id)?;
+
+        let target = match level {
+            VolumeLevel::Scalar(scalar) => {
+                Volume((scalar.clamp(0.0, 1.0) as f64 * Volume::NORMAL.0 as f64) as u32)
+            }
+            VolumeLevel::Db(db) => Volume::from(VolumeDB(db.min(0.0) as f64)),
+        };
+        // Scaled rather than set, so the balance between channels is kept
+        let mut volume = device.volume;
+        if volume.scale(target).is_none() {
+            return Err(format!("Failed to scale volume: {}", id));
+        }
+
+        self.expect_volume(id, to_volume_info(&volume, device.muted));
+        connection.set_volume(direction, id, &volume)
+    }
+
+    fn set_mute(&self, id: &str, muted: bool) -> Result<(), String> {
+        let mut connection = Connection::open()?;
+        let (direction, device) = connection.device(id)?;
+
+        self.expect_volume(id, to_volume_info(&device.volume, muted));
+        connection.set_mute(direction, id, muted)
+    }
+
+    fn subscribe(&self, callback: EventCallback) {
+        if let Ok(mut subscribers) = self.subscribers.lock() {
+            subscribers.push(callback);
+        }
+
+        self.listener.call_once(|| {
+            let subscribers = self.subscribers.clone();
+            let volumes = self.volumes.clone();
+            std::thread::spawn(move || listen_forever(&subscribers, &volumes));
+        });
+    }
+}
+
+/// A blocking connection to the PulseAudio server. The mainloop is only driven
+/// while waiting on an operation.
+struct Connection {
+    // Declared first so the context is dropped before its mainloop
+    context: Context,
+    mainloop: Mainloop,
+}
+
+impl Connection {
+    fn open() -> Result<Self, String> {
+        let mut proplist =
+            Proplist::new().ok_or_else(|| "Failed to create PulseAudio proplist".to_string())?;
+        let _ = proplist.set_str(properties::APPLICATION_NAME, APP_NAME);
+
+        let mainloop =
+            Mainloop::new().ok_or_else(|| "Failed to create PulseAudio mainloop".to_string())?;
+        let mut context = Context::new_with_proplist(&mainloop, APP_NAME, &proplist)
+            .ok_or_else(|| "Failed to create PulseAudio context".to_string())?;
+
+        context
+            .connect(None, ContextFlagSet::NOFLAGS, None)
+            .map_err(|e| format!("Failed to connect to PulseAudio: {}", e))?;
+
+        let mut connection = Self { context, mainloop };
+        loop {
+            connection.iterate()?;
+            match connection.context.get_state() {
+                ContextState::Ready => return Ok(connection),
+                ContextState::Failed | ContextState::Terminated => {
+                    return Err("PulseAudio connection failed".to_string())
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn iterate(&mut self) -> Result<(), String> {
+        match self.mainloop.iterate(true) {
+            IterateResult::Success(_) => Ok(()),
+            IterateResult::Quit(_) => Err("PulseAudio mainloop quit".to_string()),
+            IterateResult::Err(e) => Err(format!("PulseAudio mainloop error: {}", e)),
+        }
+    }
+
+    fn wait<T: ?Sized>(&mut self, operation: Operation<T>) -> Result<(), String> {
+        loop {
+            match operation.get_state() {
+                OperationState::Done => return Ok(()),
+                OperationState::Cancelled => {
+                    return Err("PulseAudio operation was cancelled".to_string())
+                }
+                OperationState::Running => self.iterate()?,
+            }
+        }
+    }
+
+    fn default_name(&mut self, direction: DeviceDirection) -> Result<Option<String>, String> {
+        let result = Rc::new(RefCell::new(None));
+        let out = result.clone();
+        let operation = self.context.introspect().get_server_info(move |info| {
+            let name = match direction {
+                DeviceDirection::Render => info.default_sink_name.as_ref(),
+                DeviceDirection::Capture => info.default_source_name.as_ref(),
+            };
+            *out.borrow_mut() = name.map(|n| n.to_string());
+        });
+        self.wait(operation)?;
+
+        let name = result.borrow_mut().take();
+        Ok(name)
+    }
+
+    /// Every sink or source, in server order.
+    fn devices(&mut self, direction: DeviceDirection) -> Result<Vec<PulseDevice>, String> {
+        let result = Rc::new(RefCell::new(Vec::new()));
+        let out = result.clone();
+        let introspector = self.context.introspect();
+        match direction {
+            DeviceDirection::Render => {
+                let operation = introspector.get_sink_info_list(move |item| {
+                    if let ListResult::Item(sink) = item {
+                        push_device(
+                            &out,
+                            sink.name.as_deref(),
+                            sink.description.as_deref(),
+                            sink.active_port.as_ref().map(|port| port.available),
+                            &sink.proplist,
+                            sink.volume,
+                            sink.mute,
+                        );
+                    }
+                });
+                self.wait(operation)?;
+            }
+            DeviceDirection::Capture => {
+                let operation = introspector.get_source_info_list(move |item| {
+                    // Every sink has a monitor source; those aren't microphones
+                    if let ListResult::Item(source) = item {
+                        if source.monitor_of_sink.is_none() {
+                            push_device(
+                                &out,
+                                source.name.as_deref(),
+                                source.description.as_deref(),
+                                source.active_port.as_ref().map(|port| port.available),
+                                &source.proplist,
+                                source.volume,
+                                source.mute,
+                            );
+                        }
+                    }
+                });
+                self.wait(operation)?;
+            }
+        }
+
+        let devices = result.take();
+        Ok(devices)
+    }
+
+    /// Finds a sink or source by name, along with its direction.
+    fn device(&mut self, name: &str) -> Result<(DeviceDirection, PulseDevice), String> {
+        for direction in DeviceDirection::ALL {
+            if let Some(device) = self
+                .devices(direction)?
+                .into_iter()
+                .find(|device| device.id == name)
+            {
+                return Ok((direction, device));
+            }
+        }
+        Err(format!("Unknown device: {}", name))
+    }
+
+    fn set_volume(
+        &mut self,
+        direction: DeviceDirection,
+        name: &str,
+        volume: &ChannelVolumes,
+    ) -> Result<(), String> {
+        let success = Rc::new(Cell::new(false));
+        let out = success.clone();
+        let mut introspector = self.context.introspect();
+        let callback = Some(Box::new(move |ok| out.set(ok)) as Box<dyn FnMut(bool)>);
+        let operation = match direction {
+            DeviceDirection::Render => introspector.set_sink_volume_by_name(name, volume, callback),
+            DeviceDirection::Capture => {
+                introspector.set_source_volume_by_name(name, volume, callback)
+            }
+        };
+        self.wait(operation)?;
+
+        if success.get() {
+            Ok(())
+        } else {
+            Err(format!("Failed to set volume: {}", name))
+        }
+    }
+
+    fn set_mute(
+        &mut self,
+        direction: DeviceDirection,
+        name: &str,
+        muted: bool,
+    ) -> Result<(), String> {
+        let success = Rc::new(Cell::new(false));
+        let out = success.clone();
+        let mut introspector = self.context.introspect();
+        let callback = Some(Box::new(move |ok| out.set(ok)) as Box<dyn FnMut(bool)>);
+        let operation = match direction {
+            DeviceDirection::Render => introspector.set_sink_mute_by_name(name, muted, callback),
+            DeviceDirection::Capture => introspector.set_source_mute_by_name(name, muted, callback),
+        };
+        self.wait(operation)?;
+
+        if success.get() {
+            Ok(())
+        } else {
+            Err(format!("Failed to set mute: {}", name))
+        }
+    }
+
+    fn set_default(&mut self, direction: DeviceDirection, name: &str) -> Result<(), String> {
+        let success = Rc::new(Cell::new(false));
+        let out = success.clone();
+        let operation = match direction {
+            DeviceDirection::Render => self.context.set_default_sink(name, move |ok| out.set(ok)),
+            DeviceDirection::Capture => {
+                self.context.set_default_source(name, move |ok| out.set(ok))
+            }
+        };
+        self.wait(operation)?;
+
+        if success.get() {
+            Ok(())
+        } else {
+            Err(format!("Failed to set default device: {}", name))
+        }
+    }
+}
+
+struct PulseDevice {
+    id: String,
+    name: String,
+    state: DeviceState,
+    metadata: DeviceMetadata,
+    volume: ChannelVolumes,
+    muted: bool,
+}
+
+/// Sinks and sources have no disabled state; a jack-sensed port reporting
+/// itself unavailable is the closest thing to "unplugged".
+fn push_device(
+    out: &RefCell<Vec<PulseDevice>>,
+    name: Option<&str>,
+    description: Option<&str>,
+    port_available: Option<PortAvailable>,
+    proplist: &Proplist,
+    volume: ChannelVolumes,
+    muted: bool,
+) {
+    if let Some(id) = name {
+        out.borrow_mut().push(PulseDevice {
+            id: id.to_string(),
+            name: description.unwrap_or(id).to_string(),
+            state: match port_available {
+                Some(PortAvailable::No) => DeviceState::Unplugged,
+                _ => DeviceState::Active,
+            },
+            metadata: DeviceMetadata {
+                description: proplist.get_str(properties::DEVICE_DESCRIPTION),
+                interface_name: proplist.get_str(properties::DEVICE_PRODUCT_NAME),
+                form_factor: proplist
+                    .get_str(properties::DEVICE_FORM_FACTOR)
+                    .map(|value| to_form_factor(&value))
+                    .unwrap_or_default(),
+                container_id: proplist.get_str(properties::DEVICE_BUS_PATH),
+                icon_path: proplist.get_str(properties::DEVICE_ICON_NAME),
+            },
+            volume,
+            muted,
+        });
+    }
+}
+
+/// Uses the loudest channel, the way pavucontrol's "lock channels" slider
+/// does. `set_volume` scales that channel, so a volume reads back as it was
+/// set. 100% (`Volume::NORMAL`) is a scalar of 1.0.
+fn to_volume_info(volume: &ChannelVolumes, muted: bool) -> VolumeInfo {
+    let loudest = volume.max();
+    VolumeInfo {
+        scalar: (loudest.0 as f64 / Volume::NORMAL.0 as f64) as f32,
+        db: VolumeDB::from(loudest).0.max(MIN_DB) as f32,
+        muted,
+    }
+}
+
+/// Maps the `device.form_factor` property onto the closest MMDevice form factor.
+fn to_form_factor(value: &str) -> DeviceFormFactor {
+    match value {
+        "internal" | "speaker" | "hifi" | "computer" | "portable" | "car" => {
+            DeviceFormFactor::Speakers
+        }
+        "headphone" => DeviceFormFactor::Headphones,
+        "headset" | "hands-free" => DeviceFormFactor::Headset,
+        "handset" => DeviceFormFactor::Handset,
+        "microphone" | "webcam" => DeviceFormFactor::Microphone,
+        "tv" => DeviceFormFactor::Hdmi,
+        _ => DeviceFormFactor::Unknown,
+    }
+}
+
+impl Drop for Connection {
+    fn drop(&mut self) {
+        self.context.disconnect();
+    }
+}
+
+/// Runs on its own thread for the lifetime of the app. Whenever the connection
+/// is lost, e.g. because the server restarted, the listener reconnects after a
+/// growing delay; `listen` tells the subscribers once it is back.
+fn listen_forever(subscribers: &Mutex<Vec<EventCallback>>, volumes: &KnownVolumes) {
+    let mut delay = MIN_RECONNECT_DELAY;
+    let mut reconnecting = false;
+    loop {
+        let started = Instant::now();
+        let _ = listen(subscribers, volumes, reconnecting);
+        // A connection that held for a while starts the backoff over
+        if started.elapsed() > MAX_RECONNECT_DELAY {
+            delay = MIN_RECONNECT_DELAY;
+        }
+        std::thread::sleep(delay);
+        delay = (delay * 2).min(MAX_RECONNECT_DELAY);
+        reconnecting = true;
+    }
+}
+
+/// Forwards sink, source and server (default device) changes to the
+/// subscribers until the connection fails. After a reconnect the subscribers
+/// are told the devices changed, since anything may have happened meanwhile.
+fn listen(
+    subscribers: &Mutex<Vec<EventCallback>>,
+    volumes: &KnownVolumes,
+    reconnecting: bool,
+) -> Result<(), String> {
+    let mut connection = Connection::open()?;
+
+    let devices_changed = Rc::new(Cell::new(false));
+    let server_changed = Rc::new(Cell::new(false));
+    {
+        let devices_changed = devices_changed.clone();
+        let server_changed = server_changed.clone();
+        connection.context.set_subscribe_callback(Some(Box::new(
+            move |facility, _operation, _index| match facility {
+                Some(Facility::Sink) | Some(Facility::Source) => devices_changed.set(true),
+                Some(Facility::Server) => server_changed.set(true),
+                _ => {}
+            },
+        )));
+    }
+    let operation = connection.context.subscribe(
+        InterestMaskSet::SINK | InterestMaskSet::SOURCE | InterestMaskSet::SERVER,
+        |_| {},
+    );
+    connection.wait(operation)?;
+
+    let notify = |event: DeviceEvent| {
+        if let Ok(subscribers) = subscribers.lock() {
+            for callback in subscribers.iter() {
+                callback(event.clone());
+            }
+        }
+    };
+
+    let mut last_defaults = [
+        connection.default_name(DeviceDirection::Render)?,
+        connection.default_name(DeviceDirection::Capture)?,
+    ];
+    external_volume_changes(&mut connection, volumes)?;
+    if reconnecting {
+        notify(DeviceEvent::DevicesChanged);
+    }
+    loop {
+        connection.iterate()?;
+
+        if devices_changed.take() {
+            for (id, volume) in external_volume_changes(&mut connection, volumes)? {
+                notify(DeviceEvent::VolumeChanged { id, volume });
+            }
+            notify(DeviceEvent::DevicesChanged);
+        }
+        if server_changed.take() {
+            for (direction, last_default) in
+                DeviceDirection::ALL.into_iter().zip(&mut last_defaults)
+            {
+                let default = connection.default_name(direction)?;
+                if default == *last_default {
+                    continue;
+                }
+                if let Some(id) = &default {
+                    for role in DeviceRole::ALL {
+                        notify(DeviceEvent::DefaultChanged {
+                            id: id.clone(),
+                            direction,
+                            role,
+                        });
+                    }
+                }
+                *last_default = default;
+            }
+        }
+    }
+}
+
+/// Compares every device's volume with the last known one and returns those
+/// that changed without going through the backend.
+fn external_volume_changes(
+    connection: &mut Connection,
+    volumes: &KnownVolumes,
+) -> Result<Vec<(String, VolumeInfo)>, String> {
+    let mut current = HashMap::new();
+    for direction in DeviceDirection::ALL {
+        for device in connection.devices(direction)? {
+            let volume = to_volume_info(&device.volume, device.muted);
+            current.insert(device.id, volume);
+        }
+    }
+
+    let mut known = volumes
+        .lock()
+        .map_err(|_| "Failed to lock known volumes".to_string())?;
+    let changes = current
+        .iter()
+        .filter(|(id, volume)| known.get(*id).is_some_and(|last| last != *volume))
+        .map(|(id, volume)| (id.clone(), *volume))
+        .collect();
+    *known = current;
+    Ok(changes)
+}
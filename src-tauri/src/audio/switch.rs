@@ -0,0 +1,422 @@
+use super::backend::{AudioBackend, AudioDevice, DeviceRole, VolumeLevel};
+use super::cycle::primary_role;
+use super::tracker::DefaultTracker;
+use crate::config::Config;
+use serde::Serialize;
+use std::fmt;
+use std::time::Duration;
+
+/// Pauses before each retry of roles that didn't take, doubling each time.
+const RETRY_DELAYS: [Duration; 3] = [
+    Duration::from_millis(50),
+    Duration::from_millis(100),
+    Duration::from_millis(200),
+];
+
+/// Payload of the `volume-capped` event.
+#[derive(Debug, Serialize, Clone)]
+pub struct VolumeCapped {
+    pub id: String,
+    /// The volume before it was lowered.
+    pub volume: f32,
+    pub max_volume: f32,
+}
+
+/// Why a switch failed. Sent to the frontend tagged by `kind`, as the error of
+/// `set_active_device` and as the `switch-failed` event.
+#[derive(Debug, Serialize, Clone)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum SwitchError {
+    /// The backend rejected the switch, or the device couldn't be found.
+    Failed { message: String },
+    /// The backend accepted the switch, but `roles` still had another default
+    /// after every retry. Some drivers silently ignore the request.
+    NotApplied { id: String, roles: Vec<DeviceRole> },
+}
+
+impl fmt::Display for SwitchError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SwitchError::Failed { message } => f.write_str(message),
+            SwitchError::NotApplied { id, roles } => {
+                let roles: Vec<String> = roles.iter().map(|role| format!("{:?}", role)).collect();
+                write!(f, "{} did not become the {} default", id, roles.join(", "))
+            }
+        }
+    }
+}
+
+impl From<String> for SwitchError {
+    fn from(message: String) -> Self {
+        SwitchError::Failed { message }
+    }
+}
+
+impl From<SwitchError> for String {
+    fn from(error: SwitchError) -> Self {
+        error.to_string()
+    }
+}
+
+pub struct SwitchOutcome {
+    /// Volume of the device switched away from, when it differs from its
+    /// `last_volume` in the config and should be stored there.
+    pub remembered: Option<(String, f32)>,
+    pub capped: Option<VolumeCapped>,
+}
+
+/// Makes `device` the default for `roles`, the path shared by cycling and
+/// `set_active_device`. Per-device settings in `config` are carried across the
+/// switch: volume memory and the volume cap. `config` isn't changed; the
+/// caller stores `SwitchOutcome::remembered` itself.
+///
+/// The new default is read back for every role, and roles that didn't take
+/// are retried a few times before giving up with `SwitchError::NotApplied`.
+pub fn switch_default(
+    backend: &dyn AudioBackend,
+    tracker: &DefaultTracker,
+    config: &Config,
+    device: &AudioDevice,
+    roles: &[DeviceRole],
+) -> Result<SwitchOutcome, SwitchError> {
+    // Re-selecting the current default (or cycling through a single device)
+    // isn't a move, and must leave the volume alone
+    let previous = backend
+        .default_device(device.direction, primary_role(roles))?
+        .filter(|previous| *previous != device.id);
+    let remembered = previous
+        .as_deref()
+        .and_then(|previous| remember_volume(backend, config, previous));
+
+    // Roles already on the device send no event, so they aren't expected
+    let pending = unapplied_roles(backend, device, roles);
+    let expectation = tracker.expect(device.direction, &device.id, &pending);
+    let result = backend
+        .set_default_device(&device.id, roles)
+        .map_err(SwitchError::from)
+        .and_then(|()| verify_default(backend, device, roles));
+    let applied: Vec<DeviceRole> = match &result {
+        Ok(()) => pending,
+        Err(SwitchError::NotApplied { roles: failed, .. }) => pending
+            .into_iter()
+            .filter(|role| !failed.contains(role))
+            .collect(),
+        Err(SwitchError::Failed { .. }) => Vec::new(),
+    };
+    tracker.settle(expectation, &applied);
+    result?;
+
+    if previous.is_some() {
+        // The switch itself worked; a volume that can't be restored isn't fatal
+        let _ = restore_volume(backend, config, &device.id);
+    }
+    apply_endpoint_settings(backend, config, &device.id);
+    let capped = enforce_max_volume(backend, config, &device.id).unwrap_or(None);
+
+    Ok(SwitchOutcome { remembered, capped })
+}
+
+/// Reads the defaults back and retries the roles that are still on another
+/// device. Retries aren't announced to the tracker again: the first request
+/// produced no event for those roles, so its expectations still stand.
+fn verify_default(
+    backend: &dyn AudioBackend,
+    device: &AudioDevice,
+    roles: &[DeviceRole],
+) -> Result<(), SwitchError> {
+    let mut pending = unapplied_roles(backend, device, roles);
+    for delay in RETRY_DELAYS {
+        if pending.is_empty() {
+            return Ok(());
+        }
+        std::thread::sleep(delay);
+
+        // A slow driver may have caught up in the meantime
+        pending = unapplied_roles(backend, device, &pending);
+        if !pending.is_empty() {
+            backend.set_default_device(&device.id, &pending)?;
+            pending = unapplied_roles(backend, device, &pending);
+        }
+    }
+
+    if pending.is_empty() {
+        Ok(())
+    } else {
+        Err(SwitchError::NotApplied {
+            id: device.id.clone(),
+            roles: pending,
+        })
+    }
+}
+
+/// The roles in `roles` that `device` is not the default for. A default that
+/// can't be read counts as not applied.
+fn unapplied_roles(
+    backend: &dyn AudioBackend,
+    device: &AudioDevice,
+    roles: &[DeviceRole],
+) -> Vec<DeviceRole> {
+    roles
+        .iter()
+        .copied()
+        .filter(|role| {
+            backend
+                .default_device(device.direction, *role)
+                .map_or(true, |id| id.as_deref() != Some(device.id.as_str()))
+        })
+        .collect()
+}
+
+/// Applies the endpoint settings the config pins for `id`. Each is best effort,
+/// so a device that rejects one still gets the rest.
+fn apply_endpoint_settings(backend: &dyn AudioBackend, config: &Config, id: &str) {
+    let settings = config.device(id);
+    if let Some(allowed) = settings.allow_exclusive {
+        let _ = backend.set_exclusive_mode(id, allowed);
+    }
+    if let Some(period) = settings.preferred_period {
+        let _ = backend.set_processing_period(id, period);
+    }
+    if settings.properties != Default::default() {
+        let _ = backend.set_endpoint_properties(id, &settings.properties);
+    }
+}
+
+/// Lowers the volume of `id` to its configured maximum if it is above it.
+pub fn enforce_max_volume(
+    backend: &dyn AudioBackend,
+    config: &Config,
+    id: &str,
+) -> Result<Option<VolumeCapped>, String> {
+    let Some(max_volume) = config.device(id).max_volume else {
+        return Ok(None);
+    };
+    let volume = backend.volume(id)?.scalar;
+    if volume <= max_volume {
+        return Ok(None);
+    }
+
+    backend.set_volume(id, VolumeLevel::Scalar(max_volume))?;
+    Ok(Some(VolumeCapped {
+        id: id.to_string(),
+        volume,
+        max_volume,
+    }))
+}
+
+/// Reads the current volume of a device being switched away from, if it
+/// should be remembered and differs from the one already stored.
+fn remember_volume(backend: &dyn AudioBackend, config: &Config, id: &str) -> Option<(String, f32)> {
+    let settings = config.device(id);
+    if !settings.restore_volume {
+        return None;
+    }
+    let volume = backend.volume(id).ok()?.scalar;
+    (settings.last_volume != Some(volume)).then(|| (id.to_string(), volume))
+}
+
+fn restore_volume(backend: &dyn AudioBackend, config: &Config, id: &str) -> Result<(), String> {
+    let settings = config.device(id);
+    match settings.last_volume {
+        Some(scalar) if settings.restore_volume => {
+            backend.set_volume(id, VolumeLevel::Scalar(scalar))
+        }
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::audio::backend::{DeviceDirection, DeviceState, EventCallback, VolumeInfo};
+    use crate::audio::memory::MemoryBackend;
+    use std::sync::Mutex;
+
+    /// A memory backend whose driver ignores the first `ignored` requests to
+    /// make a device the default for `role`, while still accepting them.
+    struct StubbornBackend {
+        inner: MemoryBackend,
+        role: DeviceRole,
+        ignored: Mutex<usize>,
+    }
+
+    impl StubbornBackend {
+        fn new(role: DeviceRole, ignored: usize) -> Self {
+            Self {
+                inner: MemoryBackend::speakers(),
+                role,
+                ignored: Mutex::new(ignored),
+            }
+        }
+    }
+
+    impl AudioBackend for StubbornBackend {
+        fn name(&self) -> &'static str {
+            "stubborn"
+        }
+
+        fn enumerate_devices(
+            &self,
+            direction: DeviceDirection,
+        ) -> Result<Vec<AudioDevice>, String> {
+            self.inner.enumerate_devices(direction)
+        }
+
+        fn default_device(
+            &self,
+            direction: DeviceDirection,
+            role: DeviceRole,
+        ) -> Result<Option<String>, String> {
+            self.inner.default_device(direction, role)
+        }
+
+        fn set_default_device(&self, id: &str, roles: &[DeviceRole]) -> Result<(), String> {
+            let mut ignored = self.ignored.lock().unwrap();
+            let roles: Vec<DeviceRole> = if *ignored > 0 && roles.contains(&self.role) {
+                *ignored -= 1;
+                roles.iter().copied().filter(|r| *r != self.role).collect()
+            } else {
+                roles.to_vec()
+            };
+            self.inner.set_default_device(id, &roles)
+        }
+
+        fn volume(&self, id: &str) -> Result<VolumeInfo, String> {
+            self.inner.volume(id)
+        }
+
+        fn set_volume(&self, id: &str, level: VolumeLevel) -> Result<(), String> {
+            self.inner.set_volume(id, level)
+        }
+
+        fn set_mute(&self, id: &str, muted: bool) -> Result<(), String> {
+            self.inner.set_mute(id, muted)
+        }
+
+        fn subscribe(&self, callback: EventCallback) {
+            self.inner.subscribe(callback)
+        }
+    }
+
+    fn device(backend: &dyn AudioBackend, id: &str) -> AudioDevice {
+        backend
+            .enumerate_devices(DeviceDirection::Render)
+            .unwrap()
+            .into_iter()
+            .find(|device| device.id == id)
+            .unwrap()
+    }
+
+    fn switch(
+        backend: &dyn AudioBackend,
+        config: &Config,
+        id: &str,
+    ) -> Result<SwitchOutcome, SwitchError> {
+        let device = device(backend, id);
+        switch_default(
+            backend,
+            &DefaultTracker::new(),
+            config,
+            &device,
+            &DeviceRole::ALL,
+        )
+    }
+
+    fn defaults(backend: &dyn AudioBackend) -> Vec<Option<String>> {
+        DeviceRole::ALL
+            .into_iter()
+            .map(|role| {
+                backend
+                    .default_device(DeviceDirection::Render, role)
+                    .unwrap()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn switches_every_role() {
+        let backend = MemoryBackend::speakers();
+        switch(&backend, &Config::default(), "b").unwrap();
+        assert_eq!(defaults(&backend), vec![Some("b".to_string()); 3]);
+    }
+
+    #[test]
+    fn ignored_roles_are_retried() {
+        let backend = StubbornBackend::new(DeviceRole::Communications, 2);
+        switch(&backend, &Config::default(), "b").unwrap();
+        assert_eq!(defaults(&backend), vec![Some("b".to_string()); 3]);
+    }
+
+    #[test]
+    fn roles_still_ignored_after_every_retry_are_reported() {
+        let backend = StubbornBackend::new(DeviceRole::Communications, usize::MAX);
+        let Err(SwitchError::NotApplied { id, roles }) = switch(&backend, &Config::default(), "b")
+        else {
+            panic!("switch should not have been applied");
+        };
+        assert_eq!(id, "b");
+        assert_eq!(roles, vec![DeviceRole::Communications]);
+        // The roles that took stay switched
+        assert_eq!(
+            backend
+                .default_device(DeviceDirection::Render, DeviceRole::Multimedia)
+                .unwrap()
+                .as_deref(),
+            Some("b")
+        );
+    }
+
+    #[test]
+    fn rejected_switch_fails() {
+        let backend = MemoryBackend::speakers();
+        backend.set_device_state("b", DeviceState::Disabled);
+        let device = AudioDevice {
+            state: DeviceState::Active,
+            ..device(&backend, "b")
+        };
+        let result = switch_default(
+            &backend,
+            &DefaultTracker::new(),
+            &Config::default(),
+            &device,
+            &DeviceRole::ALL,
+        );
+        assert!(matches!(result, Err(SwitchError::Failed { .. })));
+    }
+
+    #[test]
+    fn volume_is_remembered_and_restored_across_a_switch() {
+        let backend = MemoryBackend::speakers();
+        backend.set_volume("a", VolumeLevel::Scalar(0.3)).unwrap();
+        let mut config = Config::default();
+        config.device_mut("b").last_volume = Some(0.8);
+
+        let outcome = switch(&backend, &config, "b").unwrap();
+        assert_eq!(outcome.remembered, Some(("a".to_string(), 0.3)));
+        assert_eq!(backend.volume("b").unwrap().scalar, 0.8);
+    }
+
+    #[test]
+    fn reselecting_the_default_leaves_the_volume_alone() {
+        let backend = MemoryBackend::speakers();
+        let mut config = Config::default();
+        config.device_mut("a").last_volume = Some(0.8);
+
+        let outcome = switch(&backend, &config, "a").unwrap();
+        assert_eq!(outcome.remembered, None);
+        assert_eq!(backend.volume("a").unwrap().scalar, 0.5);
+    }
+
+    #[test]
+    fn volume_above_the_maximum_is_capped() {
+        let backend = MemoryBackend::speakers();
+        backend.set_volume("b", VolumeLevel::Scalar(0.9)).unwrap();
+        let mut config = Config::default();
+        config.device_mut("b").restore_volume = false;
+        config.device_mut("b").max_volume = Some(0.6);
+
+        let capped = switch(&backend, &config, "b").unwrap().capped.unwrap();
+        assert_eq!((capped.volume, capped.max_volume), (0.9, 0.6));
+        assert_eq!(backend.volume("b").unwrap().scalar, 0.6);
+    }
+}
@@ -0,0 +1,239 @@
+use super::backend::{AudioBackend, DeviceDirection, DeviceRole};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+/// Payload of the `device-changed` event.
+#[derive(Debug, Serialize, Clone)]
+pub struct DeviceChanged {
+    pub reason: ChangeReason,
+    pub direction: DeviceDirection,
+    pub id: String,
+}
+
+#[derive(Debug, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ChangeReason {
+    /// Cycled by the hotkey or the tray icon.
+    Cycle,
+    /// Changed by Windows, another app or the system sound panel.
+    External,
+}
+
+/// Remembers the default device per direction and role, so a default-change
+/// event can be told apart from the echo of a switch we made ourselves.
+#[derive(Default)]
+pub struct DefaultTracker {
+    state: Mutex<TrackerState>,
+}
+
+#[derive(Default)]
+struct TrackerState {
+    known: HashMap<(DeviceDirection, DeviceRole), String>,
+    /// Roles of switches we started whose event hasn't arrived yet.
+    expected: Vec<Expected>,
+    next_switch: u64,
+}
+
+struct Expected {
+    switch: u64,
+    id: String,
+    role: DeviceRole,
+}
+
+/// One switch announced with `DefaultTracker::expect`, until it is settled.
+#[must_use]
+pub struct Expectation {
+    switch: u64,
+    direction: DeviceDirection,
+    id: String,
+}
+
+impl DefaultTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Replaces what is known with the backend's current defaults.
+    pub fn seed(&self, backend: &dyn AudioBackend) {
+        let mut known = HashMap::new();
+        for direction in DeviceDirection::ALL {
+            for role in DeviceRole::ALL {
+                if let Ok(Some(id)) = backend.default_device(direction, role) {
+                    known.insert((direction, role), id);
+                }
+            }
+        }
+
+        if let Ok(mut state) = self.state.lock() {
+            state.known = known;
+            state.expected.clear();
+        }
+    }
+
+    /// Records a switch to `id` for `roles`. Call this before switching, since
+    /// backends may report the change before the switch call returns. Only
+    /// pass roles `id` isn't the default for yet: the others send no event.
+    pub fn expect(
+        &self,
+        direction: DeviceDirection,
+        id: &str,
+        roles: &[DeviceRole],
+    ) -> Expectation {
+        let mut switch = 0;
+        if let Ok(mut state) = self.state.lock() {
+            switch = state.next_switch;
+            state.next_switch += 1;
+            for role in roles {
+                state.expected.push(Expected {
+                    switch,
+                    id: id.to_string(),
+                    role: *role,
+                });
+            }
+        }
+        Expectation {
+            switch,
+            direction,
+            id: id.to_string(),
+        }
+    }
+
+    /// Ends a switch once it is verified or has failed. `applied` are the roles
+    /// that now default to its device; they are recorded as known, so an event
+    /// still on its way isn't mistaken for an external change. Whatever the
+    /// switch still expected is dropped, leaving other switches alone.
+    pub fn settle(&self, expectation: Expectation, applied: &[DeviceRole]) {
+        if let Ok(mut state) = self.state.lock() {
+            state
+                .expected
+                .retain(|expected| expected.switch != expectation.switch);
+            for role in applied {
+                state
+                    .known
+                    .insert((expectation.direction, *role), expectation.id.clone());
+            }
+        }
+    }
+
+    /// Notes that `id` became the `direction` default for `role`. Returns true
+    /// when nobody here asked for it, i.e. the change came from elsewhere.
+    pub fn observe(&self, direction: DeviceDirection, role: DeviceRole, id: &str) -> bool {
+        let Ok(mut state) = self.state.lock() else {
+            return false;
+        };
+
+        let expected = state
+            .expected
+            .iter()
+            .position(|expected| expected.id == id && expected.role == role);
+        let external = match expected {
+            Some(idx) => {
+                state.expected.remove(idx);
+                false
+            }
+            None => state.known.get(&(direction, role)).map(String::as_str) != Some(id),
+        };
+
+        state.known.insert((direction, role), id.to_string());
+        external
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::audio::backend::DeviceEvent;
+    use crate::audio::memory::MemoryBackend;
+    use crate::audio::switch::switch_default;
+    use crate::config::Config;
+    use std::sync::Arc;
+
+    /// A backend whose default-change events are classified by `tracker`, as
+    /// `handle_device_event` does. Returns the IDs reported as external.
+    fn watched(backend: &MemoryBackend, tracker: &Arc<DefaultTracker>) -> Arc<Mutex<Vec<String>>> {
+        tracker.seed(backend);
+        let external = Arc::new(Mutex::new(Vec::new()));
+        let (tracker, reported) = (tracker.clone(), external.clone());
+        backend.subscribe(Box::new(move |event| {
+            if let DeviceEvent::DefaultChanged {
+                id,
+                direction,
+                role,
+            } = event
+            {
+                if tracker.observe(direction, role, &id) {
+                    reported.lock().unwrap().push(id);
+                }
+            }
+        }));
+        external
+    }
+
+    #[test]
+    fn own_switch_is_not_external() {
+        let backend = MemoryBackend::speakers();
+        let tracker = Arc::new(DefaultTracker::new());
+        let external = watched(&backend, &tracker);
+
+        let device = backend
+            .enumerate_devices(DeviceDirection::Render)
+            .unwrap()
+            .into_iter()
+            .find(|device| device.id == "b")
+            .unwrap();
+        switch_default(
+            &backend,
+            &tracker,
+            &Config::default(),
+            &device,
+            &DeviceRole::ALL,
+        )
+        .unwrap();
+        assert!(external.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn change_made_elsewhere_is_external_once_per_role() {
+        let backend = MemoryBackend::speakers();
+        let tracker = Arc::new(DefaultTracker::new());
+        let external = watched(&backend, &tracker);
+
+        backend
+            .set_default_device("b", &[DeviceRole::Console, DeviceRole::Multimedia])
+            .unwrap();
+        assert_eq!(*external.lock().unwrap(), vec!["b", "b"]);
+    }
+
+    #[test]
+    fn repeated_event_for_the_known_default_is_not_external() {
+        let backend = MemoryBackend::speakers();
+        let tracker = DefaultTracker::new();
+        tracker.seed(&backend);
+        assert!(!tracker.observe(DeviceDirection::Render, DeviceRole::Console, "a"));
+        assert!(tracker.observe(DeviceDirection::Render, DeviceRole::Console, "b"));
+        assert!(!tracker.observe(DeviceDirection::Render, DeviceRole::Console, "b"));
+    }
+
+    #[test]
+    fn event_arriving_after_settle_is_not_external() {
+        let tracker = DefaultTracker::new();
+        let expectation = tracker.expect(DeviceDirection::Render, "b", &[DeviceRole::Console]);
+        tracker.settle(expectation, &[DeviceRole::Console]);
+        assert!(!tracker.observe(DeviceDirection::Render, DeviceRole::Console, "b"));
+    }
+
+    #[test]
+    fn settling_a_failed_switch_drops_only_its_own_expectations() {
+        let tracker = DefaultTracker::new();
+        let failed = tracker.expect(DeviceDirection::Render, "b", &[DeviceRole::Console]);
+        let pending = tracker.expect(DeviceDirection::Capture, "mic", &[DeviceRole::Console]);
+        tracker.settle(failed, &[]);
+
+        // Nothing switched to "b", so an event for it came from elsewhere
+        assert!(tracker.observe(DeviceDirection::Render, DeviceRole::Console, "b"));
+        // The other switch is still expected
+        assert!(!tracker.observe(DeviceDirection::Capture, DeviceRole::Console, "mic"));
+        tracker.settle(pending, &[DeviceRole::Console]);
+    }
+}
@@ -0,0 +1,123 @@
+use super::backend::{VolumeInfo, VolumeLevel};
+use super::device;
+use std::sync::Mutex;
+use windows::core::{implement, GUID, HSTRING};
+use windows::Win32::Media::Audio::Endpoints::{
+    IAudioEndpointVolume, IAudioEndpointVolumeCallback, IAudioEndpointVolumeCallback_Impl,
+};
+use windows::Win32::Media::Audio::AUDIO_VOLUME_NOTIFICATION_DATA;
+use windows::Win32::System::Com::CLSCTX_ALL;
+
+/// Passed with every change we make, so our own changes can be told apart
+/// from external ones in volume notifications.
+const EVENT_CONTEXT: GUID = GUID::from_u128(0x5f0c_2a8e_7d41_4b6a_9e1f_3c8d_52a7_b604);
+
+fn endpoint_volume(device_id: &str) -> Result<IAudioEndpointVolume, String> {
+    unsafe {
+        let device = device::enumerator()?
+            .GetDevice(&HSTRING::from(device_id))
+            .map_err(|e| format!("Failed to get device {}: {}", device_id, e))?;
+        device
+            .Activate(CLSCTX_ALL, None)
+            .map_err(|e| format!("Failed to activate endpoint volume: {}", e))
+    }
+}
+
+pub fn get_volume(device_id: &str) -> Result<VolumeInfo, String> {
+    let volume = endpoint_volume(device_id)?;
+    unsafe {
+        Ok(VolumeInfo {
+            scalar: volume
+                .GetMasterVolumeLevelScalar()
+                .map_err(|e| format!("Failed to get volume: {}", e))?,
+            db: volume
+                .GetMasterVolumeLevel()
+                .map_err(|e| format!("Failed to get volume: {}", e))?,
+            muted: volume
+                .GetMute()
+                .map_err(|e| format!("Failed to get mute: {}", e))?
+                .as_bool(),
+        })
+    }
+}
+
+pub fn set_volume(device_id: &str, level: VolumeLevel) -> Result<(), String> {
+    let volume = endpoint_volume(device_id)?;
+    unsafe {
+        match level {
+            VolumeLevel::Scalar(scalar) => {
+                volume.SetMasterVolumeLevelScalar(scalar.clamp(0.0, 1.0), &EVENT_CONTEXT)
+            }
+            VolumeLevel::Db(db) => {
+                let (mut min_db, mut max_db, mut step_db) = (0.0, 0.0, 0.0);
+                volume
+                    .GetVolumeRange(&mut min_db, &mut max_db, &mut step_db)
+                    .map_err(|e| format!("Failed to get volume range: {}", e))?;
+                volume.SetMasterVolumeLevel(db.clamp(min_db, max_db), &EVENT_CONTEXT)
+            }
+        }
+        .map_err(|e| format!("Failed to set volume: {}", e))
+    }
+}
+
+pub fn set_mute(device_id: &str, muted: bool) -> Result<(), String> {
+    let volume = endpoint_volume(device_id)?;
+    unsafe {
+        volume
+            .SetMute(muted, &EVENT_CONTEXT)
+            .map_err(|e| format!("Failed to set mute: {}", e))
+    }
+}
+
+/// Calls `on_change` whenever the volume or mute of one endpoint is changed by
+/// someone else, until dropped.
+pub struct VolumeListener {
+    volume: IAudioEndpointVolume,
+    callback: IAudioEndpointVolumeCallback,
+}
+
+impl VolumeListener {
+    pub fn register<F>(device_id: &str, on_change: F) -> Result<Self, String>
+    where
+        F: Fn() + Send + 'static,
+    {
+        let volume = endpoint_volume(device_id)?;
+        let callback: IAudioEndpointVolumeCallback = VolumeCallback {
+            on_change: Mutex::new(Box::new(on_change)),
+        }
+        .into();
+        unsafe {
+            volume
+                .RegisterControlChangeNotify(&callback)
+                .map_err(|e| format!("Failed to register volume notifications: {}", e))?;
+        }
+        Ok(Self { volume, callback })
+    }
+}
+
+impl Drop for VolumeListener {
+    fn drop(&mut self) {
+        unsafe {
+            let _ = self.volume.UnregisterControlChangeNotify(&self.callback);
+        }
+    }
+}
+
+#[implement(IAudioEndpointVolumeCallback)]
+struct VolumeCallback {
+    on_change: Mutex<Box<dyn Fn() + Send>>,
+}
+
+impl IAudioEndpointVolumeCallback_Impl for VolumeCallback_Impl {
+    fn OnNotify(&self, pnotify: *mut AUDIO_VOLUME_NOTIFICATION_DATA) -> windows::core::Result<()> {
+        let Some(data) = (unsafe { pnotify.as_ref() }) else {
+            return Ok(());
+        };
+        if data.guidEventContext != EVENT_CONTEXT {
+            if let Ok(on_change) = self.on_change.lock() {
+                on_change();
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,222 @@
+use super::backend::{
+    AudioBackend, AudioDevice, AudioFormat, DeviceDirection, DeviceEvent, DeviceFormats,
+    DeviceRole, EndpointProperties, EventCallback, ProcessingPeriod, VolumeInfo, VolumeLevel,
+};
+use super::notification::EndpointWatcher;
+use super::worker::AudioWorker;
+use super::{device, format, policy, property, volume};
+use std::sync::{Arc, Mutex};
+use windows::Win32::Media::Audio::{
+    eCapture, eCommunications, eConsole, eMultimedia, eRender, EDataFlow, ERole,
+};
+
+impl From<DeviceDirection> for EDataFlow {
+    fn from(direction: DeviceDirection) -> Self {
+        match direction {
+            DeviceDirection::Render => eRender,
+            DeviceDirection::Capture => eCapture,
+        }
+    }
+}
+
+impl From<DeviceRole> for ERole {
+    fn from(role: DeviceRole) -> Self {
+        match role {
+            DeviceRole::Console => eConsole,
+            DeviceRole::Multimedia => eMultimedia,
+            DeviceRole::Communications => eCommunications,
+        }
+    }
+}
+
+/// Windows Core Audio backend built on `device` (MMDevice API) and `policy`
+/// (the undocumented IPolicyConfig interface). Events come from an endpoint
+/// notification client, so switches made by Windows or other apps are seen too.
+///
+/// Every request runs on the audio worker, whichever thread it comes from.
+pub struct WasapiBackend {
+    worker: Arc<AudioWorker>,
+    subscribers: Arc<Mutex<Vec<EventCallback>>>,
+    watcher: Mutex<Option<EndpointWatcher>>,
+}
+
+impl WasapiBackend {
+    pub fn new() -> Self {
+        Self {
+            worker: Arc::new(AudioWorker::start()),
+            subscribers: Arc::default(),
+            watcher: Mutex::default(),
+        }
+    }
+}
+
+impl Default for WasapiBackend {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl AudioBackend for WasapiBackend {
+    fn name(&self) -> &'static str {
+        "wasapi"
+    }
+
+    fn enumerate_devices(&self, direction: DeviceDirection) -> Result<Vec<AudioDevice>, String> {
+        self.worker
+            .run(move || device::enumerate_devices(direction))
+    }
+
+    fn default_device(
+        &self,
+        direction: DeviceDirection,
+        role: DeviceRole,
+    ) -> Result<Option<String>, String> {
+        self.worker
+            .run(move || device::get_default_device_id(direction, role.into()))
+    }
+
+    fn set_default_device(&self, id: &str, roles: &[DeviceRole]) -> Result<(), String> {
+        let id = id.to_string();
+        let e_roles: Vec<ERole> = roles.iter().map(|&role| role.into()).collect();
+        self.worker
+            .run(move || policy::set_default_device_for_roles(&id, &e_roles))
+    }
+
+    fn volume(&self, id: &str) -> Result<VolumeInfo, String> {
+        let id = id.to_string();
+        self.worker.run(move || volume::get_volume(&id))
+    }
+
+    fn set_volume(&self, id: &str, level: VolumeLevel) -> Result<(), String> {
+        let id = id.to_string();
+        self.worker.run(move || volume::set_volume(&id, level))
+    }
+
+    fn set_mute(&self, id: &str, muted: bool) -> Result<(), String> {
+        let id = id.to_string();
+        self.worker.run(move || volume::set_mute(&id, muted))
+    }
+
+    fn device_formats(&self, id: &str) -> Result<DeviceFormats, String> {
+        let id = id.to_string();
+        self.worker.run(move || format::get_device_formats(&id))
+    }
+
+    fn set_device_format(&self, id: &str, format: AudioFormat) -> Result<(), String> {
+        let id = id.to_string();
+        self.worker
+            .run(move || format::set_device_format(&id, format))
+    }
+
+    fn reset_device_format(&self, id: &str) -> Result<(), String> {
+        let id = id.to_string();
+        self.worker.run(move || format::reset_device_format(&id))
+    }
+
+    fn exclusive_mode(&self, id: &str) -> Result<bool, String> {
+        let id = id.to_string();
+        self.worker.run(move || {
+            let allowed = property::read_property(&id, &property::ALLOW_EXCLUSIVE)?;
+            Ok(allowed.is_none_or(|value| value != 0))
+        })
+    }
+
+    fn set_exclusive_mode(&self, id: &str, allowed: bool) -> Result<(), String> {
+        let id = id.to_string();
+        self.worker.run(move || {
+            property::write_property(&id, &property::ALLOW_EXCLUSIVE, &(allowed as u32))
+        })
+    }
+
+    fn processing_period(&self, id: &str) -> Result<ProcessingPeriod, String> {
+        let id = id.to_string();
+        self.worker.run(move || policy::get_processing_period(&id))
+    }
+
+    fn set_processing_period(&self, id: &str, period: f32) -> Result<(), String> {
+        let id = id.to_string();
+        self.worker
+            .run(move || policy::set_processing_period(&id, period))
+    }
+
+    fn endpoint_properties(&self, id: &str) -> Result<EndpointProperties, String> {
+        let id = id.to_string();
+        self.worker.run(move || read_endpoint_properties(&id))
+    }
+
+    fn set_endpoint_properties(
+        &self,
+        id: &str,
+        properties: &EndpointProperties,
+    ) -> Result<(), String> {
+        let id = id.to_string();
+        let properties = properties.clone();
+        self.worker
+            .run(move || write_endpoint_properties(&id, &properties))
+    }
+
+    fn set_endpoint_visibility(&self, id: &str, visible: bool) -> Result<(), String> {
+        let id = id.to_string();
+        self.worker
+            .run(move || policy::set_endpoint_visibility(&id, visible))
+    }
+
+    fn subscribe(&self, callback: EventCallback) {
+        if let Ok(mut subscribers) = self.subscribers.lock() {
+            subscribers.push(callback);
+        }
+
+        let Ok(mut watcher) = self.watcher.lock() else {
+            return;
+        };
+        if watcher.is_none() {
+            let subscribers = self.subscribers.clone();
+            *watcher = EndpointWatcher::start(self.worker.clone(), move |event: DeviceEvent| {
+                if let Ok(subscribers) = subscribers.lock() {
+                    for callback in subscribers.iter() {
+                        callback(event.clone());
+                    }
+                }
+            })
+            .ok();
+        }
+    }
+}
+
+fn read_endpoint_properties(id: &str) -> Result<EndpointProperties, String> {
+    let listen = match device::get_device_direction(id)? {
+        DeviceDirection::Capture => property::read_property(id, &property::LISTEN_ENABLED)?,
+        DeviceDirection::Render => None,
+    };
+    Ok(EndpointProperties {
+        disable_enhancements: property::read_property(id, &property::DISABLE_SYSFX)?
+            .map(|value| value != 0),
+        listen,
+        listen_target: listen.map(|_| {
+            property::read_property(id, &property::LISTEN_TARGET)
+                .ok()
+                .flatten()
+                .unwrap_or_default()
+        }),
+    })
+}
+
+fn write_endpoint_properties(id: &str, properties: &EndpointProperties) -> Result<(), String> {
+    if (properties.listen.is_some() || properties.listen_target.is_some())
+        && device::get_device_direction(id)? != DeviceDirection::Capture
+    {
+        return Err("Only capture devices can be listened to".to_string());
+    }
+
+    if let Some(disabled) = properties.disable_enhancements {
+        property::write_property(id, &property::DISABLE_SYSFX, &(disabled as u32))?;
+    }
+    // Point the listener at its target before turning it on
+    if let Some(target) = &properties.listen_target {
+        property::write_property(id, &property::LISTEN_TARGET, target)?;
+    }
+    if let Some(listen) = properties.listen {
+        property::write_property(id, &property::LISTEN_ENABLED, &listen)?;
+    }
+    Ok(())
+}
@@ -0,0 +1,63 @@
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::mpsc::{self, Sender};
+use std::sync::Mutex;
+use std::thread::ThreadId;
+use windows::Win32::System::Com::{CoInitializeEx, COINIT_APARTMENTTHREADED};
+
+type Job = Box<dyn FnOnce() + Send>;
+
+/// A long-lived thread that owns the COM apartment every Core Audio request is
+/// made from. COM is initialized once, and the enumerator and IPolicyConfig
+/// objects cached by `device` and `policy` are created on it and reused.
+///
+/// Callers on any thread hand it requests through a channel, so their own COM
+/// state (or lack of it) no longer matters.
+pub struct AudioWorker {
+    sender: Mutex<Sender<Job>>,
+    thread: ThreadId,
+}
+
+impl AudioWorker {
+    pub fn start() -> Self {
+        let (sender, receiver) = mpsc::channel::<Job>();
+        let handle = std::thread::spawn(move || {
+            // IPolicyConfig wants a single-threaded apartment. Nothing here
+            // registers for callbacks, so no message loop is needed
+            let _ = unsafe { CoInitializeEx(None, COINIT_APARTMENTTHREADED) };
+
+            for job in receiver {
+                // A request that panics must not take the worker down with it
+                let _ = panic::catch_unwind(AssertUnwindSafe(job));
+            }
+        });
+
+        Self {
+            sender: Mutex::new(sender),
+            thread: handle.thread().id(),
+        }
+    }
+
+    /// Runs `job` on the worker thread and waits for its result.
+    pub fn run<T, F>(&self, job: F) -> Result<T, String>
+    where
+        T: Send + 'static,
+        F: FnOnce() -> Result<T, String> + Send + 'static,
+    {
+        // Requests made from a job would wait on themselves
+        if std::thread::current().id() == self.thread {
+            return job();
+        }
+
+        let (reply, result) = mpsc::channel();
+        self.sender
+            .lock()
+            .map_err(|_| "Failed to lock audio worker".to_string())?
+            .send(Box::new(move || {
+                let _ = reply.send(job());
+            }))
+            .map_err(|_| "The audio worker has stopped".to_string())?;
+        result
+            .recv()
+            .map_err(|_| "The audio worker failed to answer".to_string())?
+    }
+}
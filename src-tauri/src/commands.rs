@@ -1,74 +1,455 @@
-use crate::audio::{device, policy};
-use tauri::command;
+use crate::audio::{
+    AudioBackend, AudioDevice, AudioFormat, DeviceDirection, DeviceFormats, DeviceRole,
+    EndpointProperties, ProcessingPeriod, SharedBackend, SwitchError, VolumeInfo, VolumeLevel,
+};
+use crate::config::bundle::{self, ConfigBundle, ImportMode, ImportReport};
+use crate::config::validate::{self, ConfigError, FieldError, KnownDevices};
+use crate::config::{AppState, Config, ConfigRecovery};
+use tauri::{command, Manager};
+
+// Every command hands its work to `run_blocking`: Core Audio requests wait on
+// the audio worker, and config reads and writes hit the disk. Slow drivers then
+// no longer hold up the IPC thread or the settings window.
+
+/// Runs `work` on the blocking pool. It is spawned rather than run inside the
+/// command's future, so it finishes even if that future is dropped; shared
+/// state is never left half-updated.
+async fn run_blocking<T, E, F>(work: F) -> Result<T, E>
+where
+    T: Send + 'static,
+    E: From<String> + Send + 'static,
+    F: FnOnce() -> Result<T, E> + Send + 'static,
+{
+    tauri::async_runtime::spawn_blocking(work)
+        .await
+        .map_err(|e| E::from(format!("Command failed: {}", e)))?
+}
+
+fn update_config(
+    app_handle: &tauri::AppHandle,
+    change: impl FnOnce(&mut Config),
+) -> Result<(), String> {
+    app_handle.state::<AppState>().update(change)
+}
+
+/// Lists outputs followed by inputs.
+fn all_devices(backend: &dyn AudioBackend) -> Result<Vec<AudioDevice>, String> {
+    let mut devices = Vec::new();
+    for direction in DeviceDirection::ALL {
+        devices.extend(backend.enumerate_devices(direction)?);
+    }
+    Ok(devices)
+}
+
+/// Lists devices of one direction, or outputs followed by inputs when no
+/// direction is given.
+#[command]
+pub async fn get_audio_devices(
+    direction: Option<DeviceDirection>,
+    backend: tauri::State<'_, SharedBackend>,
+) -> Result<Vec<AudioDevice>, String> {
+    let backend = backend.inner().clone();
+    run_blocking(move || match direction {
+        Some(direction) => backend.enumerate_devices(direction),
+        None => all_devices(backend.as_ref()),
+    })
+    .await
+}
+
+/// Makes `id` the default for `roles`, or for every role when none are given.
+#[command]
+pub async fn set_active_device(
+    app_handle: tauri::AppHandle,
+    id: String,
+    roles: Option<Vec<DeviceRole>>,
+) -> Result<(), SwitchError> {
+    let roles = roles.unwrap_or_else(|| DeviceRole::ALL.to_vec());
+    run_blocking(move || crate::audio::set_active_device(&app_handle, &id, &roles)).await
+}
 
 #[command]
-pub fn get_audio_devices() -> Result<Vec<device::AudioDevice>, String> {
-    device::enumerate_devices()
+pub async fn get_volume(
+    id: String,
+    backend: tauri::State<'_, SharedBackend>,
+) -> Result<VolumeInfo, String> {
+    let backend = backend.inner().clone();
+    run_blocking(move || backend.volume(&id)).await
 }
 
+/// `level` is `{ "scalar": 0.0..1.0 }` or `{ "db": decibels }`.
 #[command]
-pub fn set_active_device(app_handle: tauri::AppHandle, id: String) -> Result<(), String> {
-    policy::set_default_device(&id)?;
-    let _ = crate::audio::update_tray_icon(&app_handle);
-    Ok(())
+pub async fn set_volume(
+    id: String,
+    level: VolumeLevel,
+    backend: tauri::State<'_, SharedBackend>,
+) -> Result<(), String> {
+    let backend = backend.inner().clone();
+    run_blocking(move || backend.set_volume(&id, level)).await
 }
 
 #[command]
-pub fn get_config(
-    state: tauri::State<'_, std::sync::Mutex<crate::config::ConfigManager>>,
-) -> Result<crate::config::Config, String> {
-    let manager = state
-        .lock()
-        .map_err(|_| "Failed to lock config manager".to_string())?;
-    Ok(manager.load())
+pub async fn set_mute(
+    id: String,
+    muted: bool,
+    backend: tauri::State<'_, SharedBackend>,
+) -> Result<(), String> {
+    let backend = backend.inner().clone();
+    run_blocking(move || backend.set_mute(&id, muted)).await
 }
 
+/// Current, default and mix format of a device's shared-mode stream.
 #[command]
-pub fn set_excluded_devices(
+pub async fn get_device_format(
+    id: String,
+    backend: tauri::State<'_, SharedBackend>,
+) -> Result<DeviceFormats, String> {
+    let backend = backend.inner().clone();
+    run_blocking(move || backend.device_formats(&id)).await
+}
+
+#[command]
+pub async fn set_device_format(
+    id: String,
+    format: AudioFormat,
+    backend: tauri::State<'_, SharedBackend>,
+) -> Result<(), String> {
+    let backend = backend.inner().clone();
+    run_blocking(move || backend.set_device_format(&id, format)).await
+}
+
+#[command]
+pub async fn reset_device_format(
+    id: String,
+    backend: tauri::State<'_, SharedBackend>,
+) -> Result<(), String> {
+    let backend = backend.inner().clone();
+    run_blocking(move || backend.reset_device_format(&id)).await
+}
+
+/// Whether applications may take exclusive control of a device.
+#[command]
+pub async fn get_exclusive_mode(
+    id: String,
+    backend: tauri::State<'_, SharedBackend>,
+) -> Result<bool, String> {
+    let backend = backend.inner().clone();
+    run_blocking(move || backend.exclusive_mode(&id)).await
+}
+
+/// Applies the exclusive-mode setting now and remembers it for the device, so
+/// it is applied again whenever the device is selected.
+#[command]
+pub async fn set_exclusive_mode(
     app_handle: tauri::AppHandle,
-    ids: Vec<String>,
-    state: tauri::State<'_, std::sync::Mutex<crate::config::ConfigManager>>,
+    id: String,
+    allowed: bool,
+    backend: tauri::State<'_, SharedBackend>,
 ) -> Result<(), String> {
-    {
-        let manager = state
-            .lock()
-            .map_err(|_| "Failed to lock config manager".to_string())?;
-        let mut config = manager.load();
-        config.excluded_device_ids = ids;
-        manager.save(&config)?;
-    }
+    let backend = backend.inner().clone();
+    run_blocking(move || {
+        backend.set_exclusive_mode(&id, allowed)?;
+        update_config(&app_handle, |config| {
+            config.device_mut(&id).allow_exclusive = Some(allowed);
+        })
+    })
+    .await
+}
 
-    let _ = crate::audio::update_tray_icon(&app_handle);
-    Ok(())
+/// The current, default and minimum engine periods of a device.
+#[command]
+pub async fn get_processing_period(
+    id: String,
+    backend: tauri::State<'_, SharedBackend>,
+) -> Result<ProcessingPeriod, String> {
+    let backend = backend.inner().clone();
+    run_blocking(move || backend.processing_period(&id)).await
 }
+
+/// Applies a preferred engine period now and remembers it for the device.
+/// `None` goes back to the default period and forgets the preference.
 #[command]
-pub fn save_config(
+pub async fn set_processing_period(
     app_handle: tauri::AppHandle,
-    config: crate::config::Config,
-    state: tauri::State<'_, std::sync::Mutex<crate::config::ConfigManager>>,
+    id: String,
+    period: Option<f32>,
+    backend: tauri::State<'_, SharedBackend>,
 ) -> Result<(), String> {
-    {
-        let manager = state
-            .lock()
-            .map_err(|_| "Failed to lock config manager".to_string())?;
+    let backend = backend.inner().clone();
+    run_blocking(move || {
+        let applied = match period {
+            Some(period) => period,
+            None => backend.processing_period(&id)?.default,
+        };
+        backend.set_processing_period(&id, applied)?;
+        update_config(&app_handle, |config| {
+            config.device_mut(&id).preferred_period = period;
+        })
+    })
+    .await
+}
 
-        manager.save(&config)?;
-    }
+/// Enhancement and listen-to-device settings of a device.
+#[command]
+pub async fn get_endpoint_properties(
+    id: String,
+    backend: tauri::State<'_, SharedBackend>,
+) -> Result<EndpointProperties, String> {
+    let backend = backend.inner().clone();
+    run_blocking(move || backend.endpoint_properties(&id)).await
+}
+
+/// Writes the given properties now and remembers them for the device, so they
+/// are written again whenever the device is selected.
+#[command]
+pub async fn set_endpoint_properties(
+    app_handle: tauri::AppHandle,
+    id: String,
+    properties: EndpointProperties,
+    backend: tauri::State<'_, SharedBackend>,
+) -> Result<(), String> {
+    let backend = backend.inner().clone();
+    run_blocking(move || {
+        backend.set_endpoint_properties(&id, &properties)?;
+        update_config(&app_handle, |config| {
+            config.device_mut(&id).properties.merge(&properties);
+        })
+    })
+    .await
+}
 
-    // Re-register hotkey
+/// Shows or hides a device for the whole system and keeps track of it in the
+/// config's hidden list.
+#[command]
+pub async fn set_endpoint_visibility(
+    app_handle: tauri::AppHandle,
+    id: String,
+    visible: bool,
+    backend: tauri::State<'_, SharedBackend>,
+) -> Result<(), String> {
+    let backend = backend.inner().clone();
+    run_blocking(move || {
+        // Look the name up first; a hidden device may not report a useful one
+        let mut name = None;
+        for direction in DeviceDirection::ALL {
+            if let Some(device) = backend
+                .enumerate_devices(direction)?
+                .into_iter()
+                .find(|d| d.id == id)
+            {
+                name = Some(device.name);
+                break;
+            }
+        }
+
+        backend.set_endpoint_visibility(&id, visible)?;
+        update_config(&app_handle, |config| {
+            config.hidden_devices.retain(|hidden| hidden.id != id);
+            if !visible {
+                config.hidden_devices.push(crate::config::HiddenDevice {
+                    name: name.unwrap_or_else(|| id.clone()),
+                    id: id.clone(),
+                });
+            }
+        })?;
+
+        let _ = crate::audio::update_tray_icon(&app_handle);
+        Ok(())
+    })
+    .await
+}
+
+#[command]
+pub async fn get_config(state: tauri::State<'_, AppState>) -> Result<Config, String> {
+    Ok(state.config())
+}
+
+/// Reports, once, that a bad `config.json` was replaced at startup.
+#[command]
+pub async fn take_config_recovery(
+    state: tauri::State<'_, AppState>,
+) -> Result<Option<ConfigRecovery>, String> {
+    Ok(state.take_recovery())
+}
+
+/// Reads `config.json` again, for edits made outside the app, and applies
+/// the hotkeys and tray state it describes. The file is validated like
+/// `save_config`; an invalid one is left unapplied.
+#[command]
+pub async fn reload_config(app_handle: tauri::AppHandle) -> Result<Config, ConfigError> {
+    run_blocking(move || {
+        let state = app_handle.state::<AppState>();
+        state.reload(|config| check_config(&app_handle, config))?;
+        let config = state.config();
+        apply_config(&app_handle, &config)?;
+        Ok(config)
+    })
+    .await
+}
+
+#[command]
+pub async fn set_excluded_devices(
+    app_handle: tauri::AppHandle,
+    ids: Vec<String>,
+    direction: Option<DeviceDirection>,
+) -> Result<(), String> {
+    run_blocking(move || {
+        update_config(&app_handle, |config| {
+            *config.excluded_ids_mut(direction.unwrap_or(DeviceDirection::Render)) = ids;
+        })?;
+
+        let _ = crate::audio::update_tray_icon(&app_handle);
+        Ok(())
+    })
+    .await
+}
+
+/// Lists the problems with `config`, the same checks `save_config` makes.
+/// An empty list means it can be saved.
+#[command]
+pub async fn validate_config(
+    app_handle: tauri::AppHandle,
+    config: Config,
+) -> Result<Vec<FieldError>, String> {
+    run_blocking(move || Ok(check_config(&app_handle, &config))).await
+}
+
+/// Replaces the config as a whole. Nothing is written unless every field is
+/// valid; otherwise the problems come back as `ConfigError::Invalid`.
+#[command]
+pub async fn save_config(app_handle: tauri::AppHandle, config: Config) -> Result<(), ConfigError> {
+    run_blocking(move || {
+        let errors = check_config(&app_handle, &config);
+        if !errors.is_empty() {
+            return Err(ConfigError::Invalid { errors });
+        }
+
+        app_handle.state::<AppState>().save(config.clone())?;
+        apply_config(&app_handle, &config)?;
+        Ok(())
+    })
+    .await
+}
+
+/// Validates `config` against the devices the system knows. When they can't
+/// be listed, device IDs are left unchecked rather than all rejected.
+pub fn check_config(app_handle: &tauri::AppHandle, config: &Config) -> Vec<FieldError> {
+    let backend = crate::audio::backend(app_handle);
+    let saved = app_handle.state::<AppState>().config();
+    let known = all_devices(backend.as_ref())
+        .ok()
+        .map(|devices| KnownDevices::new(&devices, &saved));
+    validate::validate(config, known.as_ref())
+}
+
+/// Asks where to save the current settings and writes them there as a
+/// `ConfigBundle`. Returns the file written, or `None` when cancelled.
+#[command]
+pub async fn export_config(app_handle: tauri::AppHandle) -> Result<Option<String>, String> {
+    use tauri_plugin_dialog::DialogExt;
+    run_blocking(move || {
+        let Some(path) = app_handle
+            .dialog()
+            .file()
+            .add_filter("SoundSwitch settings", &["json"])
+            .set_file_name("soundswitch-settings.json")
+            .blocking_save_file()
+        else {
+            return Ok(None);
+        };
+        let path = path
+            .into_path()
+            .map_err(|e| format!("Invalid file path: {}", e))?;
+
+        // Names only make the bundle easier to read elsewhere; export without them if need be
+        let devices = all_devices(crate::audio::backend(&app_handle).as_ref()).unwrap_or_default();
+        let version = app_handle.package_info().version.to_string();
+        let bundle =
+            ConfigBundle::new(&app_handle.state::<AppState>().config(), version, &devices)?;
+        let content = serde_json::to_string_pretty(&bundle)
+            .map_err(|e| format!("Failed to serialize settings: {}", e))?;
+        std::fs::write(&path, content)
+            .map_err(|e| format!("Failed to write settings file: {}", e))?;
+
+        Ok(Some(path.display().to_string()))
+    })
+    .await
+}
+
+/// Asks for a file written by `export_config` and imports it, merged into or
+/// replacing the current settings. The result is validated like `save_config`,
+/// except that devices missing on this machine are reported instead of
+/// rejected. Returns `None` when cancelled.
+#[command]
+pub async fn import_config(
+    app_handle: tauri::AppHandle,
+    mode: ImportMode,
+) -> Result<Option<ImportReport>, ConfigError> {
+    use tauri_plugin_dialog::DialogExt;
+    run_blocking(move || {
+        let Some(path) = app_handle
+            .dialog()
+            .file()
+            .add_filter("SoundSwitch settings", &["json"])
+            .blocking_pick_file()
+        else {
+            return Ok(None);
+        };
+        let path = path
+            .into_path()
+            .map_err(|e| format!("Invalid file path: {}", e))?;
+
+        let content = std::fs::read_to_string(&path)
+            .map_err(|e| format!("Failed to read settings file: {}", e))?;
+        let bundle = ConfigBundle::from_json(&content)?;
+        let imported = bundle.config()?;
+        // The import doesn't need the device list; if it can't be read, every
+        // device the bundle mentions is reported as missing instead
+        let devices = all_devices(crate::audio::backend(&app_handle).as_ref()).unwrap_or_default();
+        let report = ImportReport::new(&bundle, &imported.config, &devices);
+
+        let state = app_handle.state::<AppState>();
+        let config = bundle::import(&state.config(), imported, mode);
+        let errors = validate::validate(&config, None);
+        if !errors.is_empty() {
+            return Err(ConfigError::Invalid { errors });
+        }
+
+        state.save(config.clone())?;
+        apply_config(&app_handle, &config)?;
+        Ok(Some(report))
+    })
+    .await
+}
+
+/// Re-registers the hotkeys and refreshes the tray icon after the config as a
+/// whole was replaced.
+pub fn apply_config(app_handle: &tauri::AppHandle, config: &Config) -> Result<(), String> {
+    use tauri_plugin_global_shortcut::GlobalShortcutExt;
+    let _ = app_handle.global_shortcut().unregister_all();
+    let registered = register_hotkeys(app_handle, config);
+
+    let _ = crate::audio::update_tray_icon(app_handle);
+    registered
+}
+
+/// Registers the cycle hotkey of every direction. All of them are attempted;
+/// the first failure is returned.
+pub fn register_hotkeys(app_handle: &tauri::AppHandle, config: &Config) -> Result<(), String> {
     use tauri_plugin_global_shortcut::{GlobalShortcutExt, Shortcut};
     let sc_manager = app_handle.global_shortcut();
-    let _ = sc_manager.unregister_all();
-    if let Some(hotkey_str) = &config.hotkey {
-        match hotkey_str.parse::<Shortcut>() {
-            Ok(shortcut) => {
-                sc_manager.register(shortcut)
-                    .map_err(|e| format!("Failed to register shortcut '{}': {}. It might be already in use by another application.", hotkey_str, e))?;
+
+    let mut result = Ok(());
+    for direction in DeviceDirection::ALL {
+        if let Some(hotkey_str) = config.hotkey(direction) {
+            let registered = match hotkey_str.parse::<Shortcut>() {
+                Ok(shortcut) => sc_manager.register(shortcut)
+                    .map_err(|e| format!("Failed to register shortcut '{}': {}. It might be already in use by another application.", hotkey_str, e)),
+                Err(e) => Err(format!("Invalid shortcut format '{}': {}", hotkey_str, e)),
+            };
+            if result.is_ok() {
+                result = registered;
             }
-            Err(e) => return Err(format!("Invalid shortcut format '{}': {}", hotkey_str, e)),
         }
     }
-
-    let _ = crate::audio::update_tray_icon(&app_handle);
-    Ok(())
+    result
 }
@@ -1,29 +1,202 @@
+use crate::audio::{DeviceDirection, DeviceRole, EndpointProperties};
 use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::collections::HashMap;
 use std::fs;
-use std::path::PathBuf;
-use tauri::Manager;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Mutex, PoisonError, RwLock};
+use std::time::{Duration, SystemTime};
+use tauri::{Emitter, Manager};
+use validate::{ConfigError, FieldError};
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+pub mod bundle;
+pub mod migrate;
+pub mod validate;
+
+/// The contents of `config.json`. Files are brought up to the current schema
+/// by `migrate` before they are parsed; fields still missing after that take
+/// their value from `Config::default()`.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(default)]
 pub struct Config {
+    /// Schema version, see `migrate::CURRENT_VERSION`.
+    pub version: u32,
     pub excluded_device_ids: Vec<String>,
     pub hotkey: Option<String>,
+    pub excluded_capture_device_ids: Vec<String>,
+    pub capture_hotkey: Option<String>,
+    /// Roles the cycle hotkey and tray click switch. Calls can stay on a headset
+    /// by leaving out Communications.
+    pub cycle_roles: Vec<DeviceRole>,
+    /// Per-device settings, keyed by device ID. Devices without an entry use
+    /// `DeviceSettings::default()`.
+    pub devices: HashMap<String, DeviceSettings>,
+    /// Endpoints hidden from the whole system (not just the cycle), so they
+    /// can be listed and shown again later.
+    pub hidden_devices: Vec<HiddenDevice>,
+    /// Fields this version doesn't know, e.g. from a newer release or added
+    /// by hand. They are kept and written back as they were.
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct HiddenDevice {
+    pub id: String,
+    /// Name at the time it was hidden.
+    pub name: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(default)]
+pub struct DeviceSettings {
+    /// Put `last_volume` back when the device becomes the default again.
+    pub restore_volume: bool,
+    /// Volume (scalar) the device had when it was last switched away from.
+    pub last_volume: Option<f32>,
+    /// Highest volume (scalar) the device may be at. Enforced whenever it
+    /// becomes the default and whenever something else raises it further.
+    pub max_volume: Option<f32>,
+    /// Allow or deny applications exclusive control of the device, applied
+    /// whenever it is selected. `None` leaves the system setting alone.
+    pub allow_exclusive: Option<bool>,
+    /// Engine processing period in milliseconds, applied whenever the device
+    /// is selected. `None` leaves the system setting alone.
+    pub preferred_period: Option<f32>,
+    /// Endpoint properties written whenever the device is selected. Fields
+    /// left `None` keep the system setting.
+    pub properties: EndpointProperties,
+}
+
+impl Default for DeviceSettings {
+    fn default() -> Self {
+        Self {
+            restore_volume: true,
+            last_volume: None,
+            max_volume: None,
+            allow_exclusive: None,
+            preferred_period: None,
+            properties: EndpointProperties::default(),
+        }
+    }
+}
+
+fn default_cycle_roles() -> Vec<DeviceRole> {
+    DeviceRole::ALL.to_vec()
 }
 
 impl Default for Config {
     fn default() -> Self {
         Self {
+            version: migrate::CURRENT_VERSION,
             excluded_device_ids: Vec::new(),
             hotkey: Some("CommandOrControl+Shift+A".to_string()),
+            excluded_capture_device_ids: Vec::new(),
+            capture_hotkey: None,
+            cycle_roles: default_cycle_roles(),
+            devices: HashMap::new(),
+            hidden_devices: Vec::new(),
+            extra: Map::new(),
         }
     }
 }
 
+impl Config {
+    /// Parses the contents of a `config.json` of any version.
+    pub fn from_json(content: &str) -> Result<Config, String> {
+        let value = serde_json::from_str(content).map_err(|e| format!("Invalid JSON: {}", e))?;
+        Self::from_value(value)
+    }
+
+    /// Like `from_json`, for a config already parsed as JSON.
+    pub fn from_value(value: Value) -> Result<Config, String> {
+        serde_json::from_value(migrate::migrate(value)?)
+            .map_err(|e| format!("Invalid config: {}", e))
+    }
+
+    pub fn excluded_ids(&self, direction: DeviceDirection) -> &[String] {
+        match direction {
+            DeviceDirection::Render => &self.excluded_device_ids,
+            DeviceDirection::Capture => &self.excluded_capture_device_ids,
+        }
+    }
+
+    pub fn excluded_ids_mut(&mut self, direction: DeviceDirection) -> &mut Vec<String> {
+        match direction {
+            DeviceDirection::Render => &mut self.excluded_device_ids,
+            DeviceDirection::Capture => &mut self.excluded_capture_device_ids,
+        }
+    }
+
+    pub fn hotkey(&self, direction: DeviceDirection) -> Option<&str> {
+        match direction {
+            DeviceDirection::Render => self.hotkey.as_deref(),
+            DeviceDirection::Capture => self.capture_hotkey.as_deref(),
+        }
+    }
+
+    pub fn device(&self, id: &str) -> DeviceSettings {
+        self.devices.get(id).cloned().unwrap_or_default()
+    }
+
+    pub fn device_mut(&mut self, id: &str) -> &mut DeviceSettings {
+        self.devices.entry(id.to_string()).or_default()
+    }
+
+    /// Every device ID the config mentions, in no particular order. May
+    /// contain repeats.
+    pub fn device_ids(&self) -> Vec<&str> {
+        let mut ids: Vec<&str> = self
+            .excluded_device_ids
+            .iter()
+            .chain(&self.excluded_capture_device_ids)
+            .chain(self.devices.keys())
+            .map(String::as_str)
+            .collect();
+        ids.extend(self.hidden_devices.iter().map(|device| device.id.as_str()));
+        ids.extend(
+            self.devices
+                .values()
+                .filter_map(|settings| settings.properties.listen_target.as_deref())
+                .filter(|id| !id.is_empty()),
+        );
+        ids
+    }
+}
+
 // Global config store
 // In a production app, we might pass this via Tauri's State management, but a lazy static or mutex is fine for this scale.
 // Actually, Tauri State is better. Implementing standard struct logic first.
 
+/// Good versions of `config.json` kept in the `backups` directory.
+const MAX_BACKUPS: usize = 5;
+
+/// Saves closer together than this count as one burst of edits, which keeps
+/// three backups: its first and its two latest. A run of saves can't rotate
+/// older snapshots out within seconds, while the config from before the latest
+/// save is still kept.
+const BACKUP_INTERVAL: Duration = Duration::from_secs(10 * 60);
+
 pub struct ConfigManager {
     config_path: PathBuf,
+    backup_dir: PathBuf,
+}
+
+/// What `ConfigManager::load` did about a `config.json` it couldn't read or
+/// parse, for telling the user.
+#[derive(Debug, Serialize, Clone)]
+pub struct ConfigRecovery {
+    pub error: String,
+    /// The file couldn't be read at all, e.g. because another program held it
+    /// open. It may well be fine, so it was left where it is.
+    pub unreadable: bool,
+    /// Where the bad file was moved to.
+    pub quarantined_to: Option<PathBuf>,
+    /// The backup that was restored; `None` when there was no good one and the
+    /// defaults are in use.
+    pub restored_from: Option<PathBuf>,
 }
 
 impl ConfigManager {
@@ -37,26 +210,418 @@ impl ConfigManager {
             // Ignore error or log it
         }
 
-        let config_path = config_dir.join("config.json");
-        Self { config_path }
+        Self {
+            config_path: config_dir.join("config.json"),
+            backup_dir: config_dir.join("backups"),
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.config_path
     }
 
-    pub fn load(&self) -> Config {
-        if let Ok(content) = fs::read_to_string(&self.config_path) {
-            if let Ok(config) = serde_json::from_str(&content) {
-                return config;
+    /// Loads the config. A file that can't be parsed is moved aside and the
+    /// newest backup that parses is restored in its place. A file that can't
+    /// be read is left alone, and the newest backup is only used in memory.
+    /// The returned recovery says what happened.
+    pub fn load(&self) -> (Config, Option<ConfigRecovery>) {
+        let content = match self.read() {
+            Ok(Some(content)) => content,
+            Ok(None) => return (Config::default(), None),
+            Err(error) => {
+                let restored = self.newest_good_backup();
+                let recovery = ConfigRecovery {
+                    error,
+                    unreadable: true,
+                    quarantined_to: None,
+                    restored_from: restored.as_ref().map(|(path, _, _)| path.clone()),
+                };
+                let config = restored.map_or_else(Config::default, |(_, _, config)| config);
+                return (config, Some(recovery));
+            }
+        };
+        let error = match parse(&content) {
+            Ok(config) => return (config, None),
+            Err(e) => e,
+        };
+
+        let quarantined_to = self.quarantine();
+        let (config, restored_from) = match self.newest_good_backup() {
+            Some((path, content, config)) => {
+                let _ = write_atomic(&self.config_path, &content);
+                (config, Some(path))
             }
+            None => (Config::default(), None),
+        };
+
+        let recovery = ConfigRecovery {
+            error,
+            unreadable: false,
+            quarantined_to,
+            restored_from,
+        };
+        (config, Some(recovery))
+    }
+
+    /// Like `load`, but a file that exists and can't be read or parsed is an
+    /// error rather than the defaults.
+    pub fn try_load(&self) -> Result<Config, String> {
+        match self.read()? {
+            Some(content) => parse(&content),
+            None => Ok(Config::default()),
         }
-        Config::default()
     }
 
+    /// The contents of `config.json`, or `None` when there is none yet.
+    fn read(&self) -> Result<Option<String>, String> {
+        match fs::read_to_string(&self.config_path) {
+            Ok(content) => Ok(Some(content)),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(format!("Failed to read config file: {}", e)),
+        }
+    }
+
+    /// The newest backup that parses, with its contents.
+    fn newest_good_backup(&self) -> Option<(PathBuf, String, Config)> {
+        self.backups().into_iter().find_map(|(_, path)| {
+            let content = fs::read_to_string(&path).ok()?;
+            let config = Config::from_json(&content).ok()?;
+            Some((path, content, config))
+        })
+    }
+
+    /// Replaces the file and keeps a backup of what was written. A crash
+    /// midway leaves either the old or the new file, never a half-written one.
     pub fn save(&self, config: &Config) -> Result<(), String> {
         let content = serde_json::to_string_pretty(config)
             .map_err(|e| format!("Failed to serialize config: {}", e))?;
 
-        fs::write(&self.config_path, content)
+        write_atomic(&self.config_path, &content)
             .map_err(|e| format!("Failed to write config file: {}", e))?;
+        self.back_up(&content);
+        Ok(())
+    }
 
+    /// Adds `content` to the backups, unless the newest backup already has
+    /// it, and drops backups beyond `MAX_BACKUPS`. When the three backups
+    /// before it are all within `BACKUP_INTERVAL`, the middle one is dropped.
+    /// Backups are never written in place, so a failed write leaves the old
+    /// ones intact.
+    fn back_up(&self, content: &str) {
+        let backups = self.backups();
+        if let Some((_, newest)) = backups.first() {
+            if fs::read_to_string(newest).is_ok_and(|backup| backup == content) {
+                return;
+            }
+        }
+
+        let _ = fs::create_dir_all(&self.backup_dir);
+        let path = self.backup_dir.join(format!("config-{}.json", timestamp()));
+        if write_atomic(&path, content).is_err() {
+            return;
+        }
+
+        // The burst keeps its first backup, the previous one and this one
+        let recent = |backup: &(u128, PathBuf)| {
+            timestamp().saturating_sub(backup.0) < BACKUP_INTERVAL.as_millis()
+        };
+        if let [previous, middle, earlier, ..] = backups.as_slice() {
+            if recent(previous) && recent(middle) && recent(earlier) {
+                let _ = fs::remove_file(&middle.1);
+            }
+        }
+        for (_, old) in self.backups().into_iter().skip(MAX_BACKUPS) {
+            let _ = fs::remove_file(old);
+        }
+    }
+
+    /// Backup files with the time they were first written, newest first.
+    fn backups(&self) -> Vec<(u128, PathBuf)> {
+        let Ok(entries) = fs::read_dir(&self.backup_dir) else {
+            return Vec::new();
+        };
+        let mut backups: Vec<(u128, PathBuf)> = entries
+            .filter_map(|entry| {
+                let path = entry.ok()?.path();
+                let stamp = path
+                    .file_name()?
+                    .to_str()?
+                    .strip_prefix("config-")?
+                    .strip_suffix(".json")?
+                    .parse()
+                    .ok()?;
+                Some((stamp, path))
+            })
+            .collect();
+        backups.sort_by_key(|backup| std::cmp::Reverse(backup.0));
+        backups
+    }
+
+    /// Moves a bad `config.json` aside, keeping it for the user to inspect.
+    fn quarantine(&self) -> Option<PathBuf> {
+        let path = self
+            .config_path
+            .with_file_name(format!("config.corrupt-{}.json", timestamp()));
+        fs::rename(&self.config_path, &path).ok()?;
+        Some(path)
+    }
+}
+
+fn parse(content: &str) -> Result<Config, String> {
+    Config::from_json(content).map_err(|e| format!("Invalid config file: {}", e))
+}
+
+/// Writes to a temporary file next to `path` and renames it over the real
+/// one. A crash midway leaves either the old or the new file.
+fn write_atomic(path: &Path, content: &str) -> io::Result<()> {
+    let temp_path = path.with_extension("json.tmp");
+    let mut file = fs::File::create(&temp_path)?;
+    file.write_all(content.as_bytes())?;
+    file.sync_all()?;
+    drop(file);
+    fs::rename(&temp_path, path)
+}
+
+/// Milliseconds since the Unix epoch, for naming backups.
+fn timestamp() -> u128 {
+    SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_millis())
+        .unwrap_or_default()
+}
+
+/// How long `remember_volume` waits before saving, so a run of switches is
+/// written once.
+const SAVE_DELAY: Duration = Duration::from_secs(5);
+
+/// The parsed config, managed as Tauri state so the hot paths (hotkeys, cycling,
+/// the tray icon) never touch the disk. Changes are written through to
+/// `config.json` before they become visible, except remembered volumes, which
+/// are saved a little later.
+pub struct AppState {
+    manager: ConfigManager,
+    config: RwLock<Configs>,
+    /// Set while a save of remembered volumes is waiting to run.
+    save_pending: AtomicBool,
+    /// Set when the config had to be recovered at startup, until the frontend
+    /// picks it up.
+    recovery: Mutex<Option<ConfigRecovery>>,
+}
+
+struct Configs {
+    current: Config,
+    /// What `config.json` holds as far as the app knows: the config as last
+    /// loaded or saved. Differs from `current` while remembered volumes wait
+    /// to be saved.
+    saved: Config,
+}
+
+impl AppState {
+    pub fn new(manager: ConfigManager) -> Self {
+        let (config, recovery) = manager.load();
+        Self {
+            manager,
+            config: RwLock::new(Configs {
+                current: config.clone(),
+                saved: config,
+            }),
+            save_pending: AtomicBool::new(false),
+            recovery: Mutex::new(recovery),
+        }
+    }
+
+    /// What was done about a bad `config.json` at startup, once.
+    pub fn take_recovery(&self) -> Option<ConfigRecovery> {
+        self.recovery
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .take()
+    }
+
+    /// A copy of the current config.
+    pub fn config(&self) -> Config {
+        self.read(Config::clone)
+    }
+
+    /// Runs `f` on the current config without copying it.
+    pub fn read<R>(&self, f: impl FnOnce(&Config) -> R) -> R {
+        let configs = self.config.read().unwrap_or_else(PoisonError::into_inner);
+        f(&configs.current)
+    }
+
+    /// Saves `config` and makes it the current one.
+    pub fn save(&self, config: Config) -> Result<(), String> {
+        self.update(|current| *current = config)
+    }
+
+    /// Applies `change` to a copy of the current config, saves it and makes it
+    /// current. The write lock is held throughout, so concurrent updates don't
+    /// lose each other's changes, and a failed save changes nothing.
+    pub fn update<R>(&self, change: impl FnOnce(&mut Config) -> R) -> Result<R, String> {
+        let mut configs = self
+            .config
+            .write()
+            .map_err(|_| "Failed to lock config".to_string())?;
+        let mut config = configs.current.clone();
+        let result = change(&mut config);
+        self.manager.save(&config)?;
+        configs.saved = config.clone();
+        configs.current = config;
+        Ok(result)
+    }
+
+    /// Stores the volume `id` had when it was switched away from, in memory
+    /// only. The next save writes it; see `remember_volume`.
+    fn set_last_volume(&self, id: &str, volume: f32) {
+        let mut configs = self.config.write().unwrap_or_else(PoisonError::into_inner);
+        configs.current.device_mut(id).last_volume = Some(volume);
+    }
+
+    /// Saves what is only in memory so far, if anything.
+    pub fn flush(&self) -> Result<(), String> {
+        let mut configs = self
+            .config
+            .write()
+            .map_err(|_| "Failed to lock config".to_string())?;
+        if configs.current == configs.saved {
+            return Ok(());
+        }
+        self.manager.save(&configs.current)?;
+        configs.saved = configs.current.clone();
         Ok(())
     }
+
+    pub fn config_path(&self) -> &Path {
+        self.manager.path()
+    }
+
+    /// Reads `config.json` again, e.g. after it was edited on disk. Returns the
+    /// new config, or `None` when the file still holds what was last loaded or
+    /// saved, as it does after the app's own saves. A file that doesn't parse,
+    /// or that `check` finds problems with, leaves the current config in
+    /// place. So does a save made while the file was checked: it wrote over
+    /// the file, and the next reload reads that. Volumes not saved yet are
+    /// kept.
+    pub fn reload(
+        &self,
+        check: impl FnOnce(&Config) -> Vec<FieldError>,
+    ) -> Result<Option<Config>, ConfigError> {
+        let config = self.manager.try_load()?;
+        let saved = self
+            .config
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .saved
+            .clone();
+        if saved == config {
+            return Ok(None);
+        }
+
+        // Checked without holding the lock, which `check` may need to read
+        let errors = check(&config);
+        if !errors.is_empty() {
+            return Err(ConfigError::Invalid { errors });
+        }
+
+        let mut configs = self.config.write().unwrap_or_else(PoisonError::into_inner);
+        if configs.saved != saved {
+            return Ok(None);
+        }
+        let mut current = config.clone();
+        for (id, settings) in &configs.current.devices {
+            let saved_volume = saved.devices.get(id).and_then(|saved| saved.last_volume);
+            if settings.last_volume != saved_volume {
+                current.device_mut(id).last_volume = settings.last_volume;
+            }
+        }
+        configs.current = current.clone();
+        configs.saved = config;
+        Ok(Some(current))
+    }
+}
+
+/// Stores the volume `id` had when it was switched away from. Switching must
+/// stay off the disk, so the volume is saved `SAVE_DELAY` later, along with any
+/// other volume remembered by then. Failing to save is reported through
+/// `config-error`.
+pub fn remember_volume(app_handle: &tauri::AppHandle, id: &str, volume: f32) {
+    let state = app_handle.state::<AppState>();
+    state.set_last_volume(id, volume);
+    if state.save_pending.swap(true, Ordering::AcqRel) {
+        return;
+    }
+
+    let app_handle = app_handle.clone();
+    std::thread::spawn(move || {
+        std::thread::sleep(SAVE_DELAY);
+        let state = app_handle.state::<AppState>();
+        state.save_pending.store(false, Ordering::Release);
+        if let Err(e) = state.flush() {
+            let _ = app_handle.emit("config-error", ConfigError::from(e));
+        }
+    });
+}
+
+/// How often `watch` looks at `config.json`.
+const WATCH_INTERVAL: Duration = Duration::from_secs(1);
+/// How long a changed file must stay unchanged before it is read.
+const SETTLE_DELAY: Duration = Duration::from_millis(200);
+
+/// Applies edits made to `config.json` outside the app, e.g. by dotfile tools.
+/// A changed file is re-read and validated; if it differs from the current
+/// config, the hotkeys and tray are updated and `config-changed` is emitted
+/// with the new config. A file that fails to parse or to validate is reported
+/// through `config-error` as a `ConfigError` and otherwise ignored, as is a
+/// hotkey that can't be registered.
+///
+/// The file is polled rather than watched. A stat a second is cheap, needs no
+/// file-watching dependency with its per-platform backends, and also covers
+/// tools that replace the file through a rename or a symlink, which watchers
+/// on the file itself lose track of. Our own saves are seen too, but read back
+/// as what was saved and are skipped.
+pub fn watch(app_handle: &tauri::AppHandle) {
+    let app_handle = app_handle.clone();
+    std::thread::spawn(move || {
+        let path = app_handle.state::<AppState>().config_path().to_path_buf();
+        // The first poll always reads the file, and `reload` compares it with
+        // the config as loaded: edits made since then aren't missed
+        let mut last_seen = None;
+        loop {
+            std::thread::sleep(WATCH_INTERVAL);
+            let seen = fingerprint(&path);
+            // Deleting the file isn't taken as a wish to reset everything
+            if seen == last_seen || seen.is_none() {
+                continue;
+            }
+            last_seen = seen;
+
+            // Let a write that is still in progress finish first
+            std::thread::sleep(SETTLE_DELAY);
+            if fingerprint(&path) != last_seen {
+                continue;
+            }
+
+            let reloaded = app_handle
+                .state::<AppState>()
+                .reload(|config| crate::commands::check_config(&app_handle, config));
+            match reloaded {
+                Ok(Some(config)) => {
+                    if let Err(e) = crate::commands::apply_config(&app_handle, &config) {
+                        let _ = app_handle.emit("config-error", ConfigError::from(e));
+                    }
+                    let _ = app_handle.emit("config-changed", config);
+                }
+                Ok(None) => {}
+                Err(e) => {
+                    let _ = app_handle.emit("config-error", e);
+                }
+            }
+        }
+    });
+}
+
+/// Changes whenever the file is written, or replaced by another one.
+fn fingerprint(path: &Path) -> Option<(SystemTime, u64)> {
+    let metadata = fs::metadata(path).ok()?;
+    Some((metadata.modified().ok()?, metadata.len()))
 }
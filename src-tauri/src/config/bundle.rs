@@ -0,0 +1,240 @@
+//! Settings files for sharing a setup between machines: a config together
+//! with what is needed to read it elsewhere.
+//!
+//! Devices are only ever shown by their system name; the app has no aliases
+//! of its own to carry. The names in a bundle are there to tell the user which
+//! device an unknown ID meant.
+
+use super::Config;
+use crate::audio::{AudioDevice, DeviceRole};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{HashMap, HashSet};
+
+/// Value of `format` in every bundle, so other JSON files are told apart.
+pub const BUNDLE_FORMAT: &str = "soundswitch-settings";
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ConfigBundle {
+    /// Always `BUNDLE_FORMAT`.
+    pub format: String,
+    /// Version of the app that exported it.
+    pub app_version: String,
+    /// Names of the devices the config mentions, as the exporting machine
+    /// called them. IDs are only meaningful there.
+    #[serde(default)]
+    pub device_names: HashMap<String, String>,
+    /// The config, with its schema version. Migrated like `config.json` when
+    /// imported, so bundles from older releases still import.
+    pub config: Value,
+}
+
+impl ConfigBundle {
+    /// Packs `config` for another machine. State that belongs to this machine
+    /// is left out: remembered volumes, and hidden devices, which are hidden
+    /// on the system itself rather than by the config.
+    pub fn new(
+        config: &Config,
+        app_version: String,
+        devices: &[AudioDevice],
+    ) -> Result<Self, String> {
+        let mut config = config.clone();
+        config.hidden_devices.clear();
+        for settings in config.devices.values_mut() {
+            settings.last_volume = None;
+        }
+
+        let ids: HashSet<&str> = config.device_ids().into_iter().collect();
+        let device_names = devices
+            .iter()
+            .filter(|device| ids.contains(device.id.as_str()))
+            .map(|device| (device.id.clone(), device.name.clone()))
+            .collect();
+
+        Ok(Self {
+            format: BUNDLE_FORMAT.to_string(),
+            app_version,
+            device_names,
+            config: serde_json::to_value(&config)
+                .map_err(|e| format!("Failed to serialize config: {}", e))?,
+        })
+    }
+
+    pub fn from_json(content: &str) -> Result<Self, String> {
+        let bundle: ConfigBundle = serde_json::from_str(content)
+            .map_err(|_| "Not a SoundSwitch settings file".to_string())?;
+        if bundle.format != BUNDLE_FORMAT {
+            return Err("Not a SoundSwitch settings file".to_string());
+        }
+        Ok(bundle)
+    }
+
+    pub fn config(&self) -> Result<Imported, String> {
+        let config = Config::from_value(self.config.clone())?;
+        let cycle_roles = self
+            .config
+            .get("cycle_roles")
+            .is_some_and(|roles| !roles.is_null())
+            .then(|| config.cycle_roles.clone());
+        Ok(Imported {
+            config,
+            cycle_roles,
+        })
+    }
+}
+
+/// The config in a bundle, as `import` takes it.
+pub struct Imported {
+    pub config: Config,
+    /// The bundle's `cycle_roles`, `None` when it has none. `config` holds the
+    /// default then, which a merge must not take for a choice.
+    pub cycle_roles: Option<Vec<DeviceRole>>,
+}
+
+/// How an imported config is combined with the current one.
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ImportMode {
+    /// Adds to the current config: exclusions are combined, and every
+    /// setting the import has replaces the current one.
+    Merge,
+    /// Takes the imported config as it is.
+    Replace,
+}
+
+/// Returns the config to save after importing `imported` into `current`. Either
+/// way this machine keeps its hidden devices and remembered volumes.
+pub fn import(current: &Config, imported: Imported, mode: ImportMode) -> Config {
+    let Imported {
+        config: imported,
+        cycle_roles,
+    } = imported;
+    let mut config = match mode {
+        ImportMode::Replace => imported,
+        ImportMode::Merge => {
+            let mut merged = current.clone();
+            merged.version = merged.version.max(imported.version);
+            add_missing(
+                &mut merged.excluded_device_ids,
+                imported.excluded_device_ids,
+            );
+            add_missing(
+                &mut merged.excluded_capture_device_ids,
+                imported.excluded_capture_device_ids,
+            );
+            // No hotkey in the import means none was set there, not "remove mine"
+            if imported.hotkey.is_some() {
+                merged.hotkey = imported.hotkey;
+            }
+            if imported.capture_hotkey.is_some() {
+                merged.capture_hotkey = imported.capture_hotkey;
+            }
+            if let Some(cycle_roles) = cycle_roles {
+                merged.cycle_roles = cycle_roles;
+            }
+            merged.devices.extend(imported.devices);
+            merged.extra.extend(imported.extra);
+            merged
+        }
+    };
+
+    config.hidden_devices = current.hidden_devices.clone();
+    for (id, settings) in &mut config.devices {
+        if settings.last_volume.is_none() {
+            settings.last_volume = current.devices.get(id).and_then(|s| s.last_volume);
+        }
+    }
+    config
+}
+
+fn add_missing(ids: &mut Vec<String>, imported: Vec<String>) {
+    for id in imported {
+        if !ids.contains(&id) {
+            ids.push(id);
+        }
+    }
+}
+
+/// A device an imported config mentions that this machine doesn't have.
+#[derive(Debug, Serialize, Clone)]
+pub struct MissingDevice {
+    pub id: String,
+    /// Name on the exporting machine, when the bundle has it.
+    pub name: Option<String>,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct ImportReport {
+    pub missing_devices: Vec<MissingDevice>,
+}
+
+impl ImportReport {
+    /// Lists the devices `bundle` mentions that aren't among `devices`. Their
+    /// settings are imported anyway, for when the device is plugged in.
+    pub fn new(bundle: &ConfigBundle, imported: &Config, devices: &[AudioDevice]) -> Self {
+        let present: HashSet<&str> = devices.iter().map(|device| device.id.as_str()).collect();
+        let mut seen = HashSet::new();
+        let missing_devices = imported
+            .device_ids()
+            .into_iter()
+            .filter(|id| !present.contains(id) && seen.insert(*id))
+            .map(|id| MissingDevice {
+                id: id.to_string(),
+                name: bundle.device_names.get(id).cloned(),
+            })
+            .collect();
+        Self { missing_devices }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn bundle(config: Value) -> ConfigBundle {
+        ConfigBundle {
+            format: BUNDLE_FORMAT.to_string(),
+            app_version: "0.1.0".to_string(),
+            device_names: HashMap::new(),
+            config,
+        }
+    }
+
+    fn current() -> Config {
+        Config {
+            cycle_roles: vec![DeviceRole::Multimedia],
+            ..Config::default()
+        }
+    }
+
+    #[test]
+    fn merge_keeps_cycle_roles_the_bundle_has_none_of() {
+        for config in [
+            json!({ "version": 1, "excluded_device_ids": ["a"] }),
+            json!({ "excluded_device_ids": ["a"], "cycle_roles": null }),
+        ] {
+            let imported = bundle(config).config().unwrap();
+            assert_eq!(imported.cycle_roles, None);
+            let merged = import(&current(), imported, ImportMode::Merge);
+            assert_eq!(merged.cycle_roles, vec![DeviceRole::Multimedia]);
+            assert_eq!(merged.excluded_device_ids, vec!["a".to_string()]);
+        }
+    }
+
+    #[test]
+    fn merge_takes_cycle_roles_the_bundle_has() {
+        let imported = bundle(json!({ "version": 1, "cycle_roles": ["console"] }))
+            .config()
+            .unwrap();
+        let merged = import(&current(), imported, ImportMode::Merge);
+        assert_eq!(merged.cycle_roles, vec![DeviceRole::Console]);
+    }
+
+    #[test]
+    fn replace_takes_the_defaults_of_missing_fields() {
+        let imported = bundle(json!({ "version": 1 })).config().unwrap();
+        let replaced = import(&current(), imported, ImportMode::Replace);
+        assert_eq!(replaced.cycle_roles, DeviceRole::ALL.to_vec());
+    }
+}
@@ -0,0 +1,76 @@
+//! Upgrades `config.json` files written by older releases.
+//!
+//! Every change to the meaning of an existing field bumps `CURRENT_VERSION`
+//! and adds a step to `MIGRATIONS` that rewrites a file of the previous
+//! version. Steps work on the raw JSON, so fields the current `Config` no
+//! longer (or doesn't yet) know are carried along instead of being dropped.
+//! New fields that read correctly from their default don't need a step.
+
+use serde_json::{Map, Value};
+
+/// Schema version of the files this release writes.
+pub const CURRENT_VERSION: u32 = 1;
+
+type Migration = fn(&mut Map<String, Value>) -> Result<(), String>;
+
+/// `MIGRATIONS[n]` turns a version `n` file into a version `n + 1` one.
+const MIGRATIONS: [Migration; CURRENT_VERSION as usize] = [v0_to_v1];
+
+/// Brings a parsed config file up to `CURRENT_VERSION`. Files from a newer
+/// release are left as they are, version included, so saving them doesn't
+/// pass them off as an older schema.
+pub fn migrate(value: Value) -> Result<Value, String> {
+    let Value::Object(mut config) = value else {
+        return Err("Config must be a JSON object".to_string());
+    };
+
+    let mut version = version_of(&config)?;
+    while let Some(step) = MIGRATIONS.get(version as usize) {
+        step(&mut config)
+            .map_err(|e| format!("Failed to migrate from version {}: {}", version, e))?;
+        version += 1;
+        config.insert("version".to_string(), Value::from(version));
+    }
+
+    Ok(Value::Object(config))
+}
+
+/// Files written before versioning have no `version` field and count as 0.
+fn version_of(config: &Map<String, Value>) -> Result<u32, String> {
+    match config.get("version") {
+        None => Ok(0),
+        Some(value) => value
+            .as_u64()
+            .and_then(|version| u32::try_from(version).ok())
+            .ok_or_else(|| format!("Invalid config version: {}", value)),
+    }
+}
+
+/// Version 0 is every file written before versioning. Fields were added one at
+/// a time, each with its own default, so any of them may be missing.
+fn v0_to_v1(config: &mut Map<String, Value>) -> Result<(), String> {
+    // A v0 file without `hotkey` had none; the default of a new config is
+    // Ctrl+Shift+A, which would register a hotkey the user never had
+    config.entry("hotkey").or_insert(Value::Null);
+
+    // Hand-edited files may have `null` for a list; it meant "none"
+    for field in [
+        "excluded_device_ids",
+        "excluded_capture_device_ids",
+        "hidden_devices",
+    ] {
+        if config.get(field).is_some_and(Value::is_null) {
+            config.insert(field.to_string(), Value::Array(Vec::new()));
+        }
+    }
+    if config.get("devices").is_some_and(Value::is_null) {
+        config.insert("devices".to_string(), Value::Object(Map::new()));
+    }
+    // An unset `cycle_roles` switched every role; without the key the
+    // default does the same
+    if config.get("cycle_roles").is_some_and(Value::is_null) {
+        config.remove("cycle_roles");
+    }
+
+    Ok(())
+}
@@ -0,0 +1,398 @@
+//! Checks a whole `Config` before it is saved, so a bad value is reported
+//! against the field it came from instead of surfacing later, e.g. when a
+//! hotkey fails to register.
+
+use super::Config;
+use crate::audio::{AudioDevice, DeviceDirection};
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::hash::Hash;
+use tauri_plugin_global_shortcut::Shortcut;
+
+/// A problem with one field. `field` is the path of the value in the config,
+/// e.g. `hotkey`, `excluded_device_ids[2]` or `devices.<id>.max_volume`.
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub struct FieldError {
+    pub field: String,
+    pub message: String,
+}
+
+impl FieldError {
+    fn new(field: impl Into<String>, message: impl Into<String>) -> Self {
+        Self {
+            field: field.into(),
+            message: message.into(),
+        }
+    }
+}
+
+/// Why a config wasn't saved. Sent to the frontend tagged by `kind`, as the
+/// error of `save_config`.
+#[derive(Debug, Serialize, Clone)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum ConfigError {
+    /// The config has invalid fields; nothing was written.
+    Invalid { errors: Vec<FieldError> },
+    /// The config is valid, but writing or applying it failed.
+    Failed { message: String },
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Invalid { errors } => {
+                let errors: Vec<String> = errors
+                    .iter()
+                    .map(|error| format!("{}: {}", error.field, error.message))
+                    .collect();
+                write!(f, "Invalid config: {}", errors.join("; "))
+            }
+            ConfigError::Failed { message } => f.write_str(message),
+        }
+    }
+}
+
+impl From<String> for ConfigError {
+    fn from(message: String) -> Self {
+        ConfigError::Failed { message }
+    }
+}
+
+impl From<ConfigError> for String {
+    fn from(error: ConfigError) -> Self {
+        error.to_string()
+    }
+}
+
+/// The device IDs a config may refer to.
+pub struct KnownDevices {
+    directions: HashMap<String, DeviceDirection>,
+    /// IDs the saved config already uses. They stay valid after the device is
+    /// removed from the system, so a config that mentions it can still be
+    /// saved.
+    saved: HashSet<String>,
+}
+
+impl KnownDevices {
+    /// `devices` are the endpoints of every direction, in any state.
+    pub fn new(devices: &[AudioDevice], saved: &Config) -> Self {
+        let directions = devices
+            .iter()
+            .map(|device| (device.id.clone(), device.direction))
+            .collect();
+        Self {
+            directions,
+            saved: saved.device_ids().into_iter().map(str::to_string).collect(),
+        }
+    }
+
+    fn check(&self, id: &str, direction: DeviceDirection) -> Option<&'static str> {
+        match self.directions.get(id) {
+            Some(&known) if known == direction => None,
+            _ if self.saved.contains(id) => None,
+            Some(_) => Some(match direction {
+                DeviceDirection::Render => "Not an output device",
+                DeviceDirection::Capture => "Not an input device",
+            }),
+            None => Some("Unknown device"),
+        }
+    }
+
+    fn contains(&self, id: &str) -> bool {
+        self.directions.contains_key(id) || self.saved.contains(id)
+    }
+}
+
+/// Returns every problem with `config`; an empty list means it can be saved.
+/// Device IDs are only checked against the system when `known` is given.
+pub fn validate(config: &Config, known: Option<&KnownDevices>) -> Vec<FieldError> {
+    let mut errors = Vec::new();
+    check_hotkeys(config, &mut errors);
+
+    for direction in DeviceDirection::ALL {
+        let field = match direction {
+            DeviceDirection::Render => "excluded_device_ids",
+            DeviceDirection::Capture => "excluded_capture_device_ids",
+        };
+        let ids = config.excluded_ids(direction);
+        check_duplicates(field, ids, &mut errors);
+        if let Some(known) = known {
+            for (i, id) in ids.iter().enumerate() {
+                if let Some(message) = known.check(id, direction) {
+                    errors.push(FieldError::new(format!("{}[{}]", field, i), message));
+                }
+            }
+        }
+    }
+
+    // An empty list is allowed and means every role, see `cycle::effective_roles`
+    check_duplicates("cycle_roles", &config.cycle_roles, &mut errors);
+
+    check_devices(config, known, &mut errors);
+
+    for (i, device) in config.hidden_devices.iter().enumerate() {
+        if device.id.is_empty() {
+            errors.push(FieldError::new(
+                format!("hidden_devices[{}].id", i),
+                "Missing device ID",
+            ));
+        }
+    }
+    let hidden: Vec<&str> = config
+        .hidden_devices
+        .iter()
+        .map(|d| d.id.as_str())
+        .collect();
+    check_duplicates("hidden_devices", &hidden, &mut errors);
+
+    errors
+}
+
+fn check_hotkeys(config: &Config, errors: &mut Vec<FieldError>) {
+    let mut parsed: Vec<(DeviceDirection, Shortcut)> = Vec::new();
+    for direction in DeviceDirection::ALL {
+        let field = match direction {
+            DeviceDirection::Render => "hotkey",
+            DeviceDirection::Capture => "capture_hotkey",
+        };
+        let Some(hotkey) = config.hotkey(direction) else {
+            continue;
+        };
+
+        match hotkey.parse::<Shortcut>() {
+            Ok(shortcut) => {
+                if let Some((other, _)) = parsed.iter().find(|(_, s)| *s == shortcut) {
+                    let message = match other {
+                        DeviceDirection::Render => "Already used to switch outputs",
+                        DeviceDirection::Capture => "Already used to switch inputs",
+                    };
+                    errors.push(FieldError::new(field, message));
+                } else {
+                    parsed.push((direction, shortcut));
+                }
+            }
+            Err(e) => errors.push(FieldError::new(
+                field,
+                format!("Invalid shortcut '{}': {}", hotkey, e),
+            )),
+        }
+    }
+}
+
+fn check_devices(config: &Config, known: Option<&KnownDevices>, errors: &mut Vec<FieldError>) {
+    // Sorted so the errors come back in the same order every time
+    let mut ids: Vec<&String> = config.devices.keys().collect();
+    ids.sort();
+
+    for id in ids {
+        let settings = &config.devices[id];
+        let field = |name: &str| format!("devices.{}.{}", id, name);
+
+        if let Some(known) = known {
+            if !known.contains(id) {
+                errors.push(FieldError::new(format!("devices.{}", id), "Unknown device"));
+            }
+        }
+
+        for (name, volume) in [
+            ("last_volume", settings.last_volume),
+            ("max_volume", settings.max_volume),
+        ] {
+            if volume.is_some_and(|v| !(0.0..=1.0).contains(&v)) {
+                errors.push(FieldError::new(field(name), "Must be between 0 and 1"));
+            }
+        }
+
+        if settings
+            .preferred_period
+            .is_some_and(|period| !(period.is_finite() && period > 0.0))
+        {
+            errors.push(FieldError::new(
+                field("preferred_period"),
+                "Must be a positive number of milliseconds",
+            ));
+        }
+
+        // Empty means the default output, which always exists
+        if let (Some(known), Some(target)) = (known, &settings.properties.listen_target) {
+            if !target.is_empty() {
+                if let Some(message) = known.check(target, DeviceDirection::Render) {
+                    errors.push(FieldError::new(field("properties.listen_target"), message));
+                }
+            }
+        }
+    }
+}
+
+/// Reports each repeat of a value in `values`, at the index it repeats at.
+fn check_duplicates<T: Eq + Hash>(field: &str, values: &[T], errors: &mut Vec<FieldError>) {
+    let mut seen = HashSet::new();
+    for (i, value) in values.iter().enumerate() {
+        if !seen.insert(value) {
+            errors.push(FieldError::new(
+                format!("{}[{}]", field, i),
+                "Listed more than once",
+            ));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::audio::memory::MemoryBackend;
+    use crate::audio::{AudioBackend, DeviceRole};
+    use crate::config::HiddenDevice;
+
+    const SPEAKERS: &str = "speakers";
+    const MICROPHONE: &str = "microphone";
+
+    fn known(saved: &Config) -> KnownDevices {
+        let backend = MemoryBackend::with_devices([
+            (SPEAKERS, "Speakers", DeviceDirection::Render),
+            (MICROPHONE, "Microphone", DeviceDirection::Capture),
+        ]);
+        let mut devices = Vec::new();
+        for direction in DeviceDirection::ALL {
+            devices.extend(backend.enumerate_devices(direction).unwrap());
+        }
+        KnownDevices::new(&devices, saved)
+    }
+
+    fn fields(errors: &[FieldError]) -> Vec<&str> {
+        errors.iter().map(|error| error.field.as_str()).collect()
+    }
+
+    #[test]
+    fn default_config_is_valid() {
+        let config = Config::default();
+        assert_eq!(validate(&config, Some(&known(&config))), vec![]);
+    }
+
+    #[test]
+    fn invalid_shortcut_is_reported_on_its_field() {
+        let config = Config {
+            capture_hotkey: Some("CommandOrControl+".to_string()),
+            ..Config::default()
+        };
+        assert_eq!(fields(&validate(&config, None)), vec!["capture_hotkey"]);
+    }
+
+    #[test]
+    fn hotkey_used_for_both_directions_is_reported_once() {
+        let config = Config {
+            hotkey: Some("CommandOrControl+Shift+A".to_string()),
+            capture_hotkey: Some("CommandOrControl+Shift+A".to_string()),
+            ..Config::default()
+        };
+        let errors = validate(&config, None);
+        assert_eq!(fields(&errors), vec!["capture_hotkey"]);
+        assert_eq!(errors[0].message, "Already used to switch outputs");
+    }
+
+    #[test]
+    fn repeated_ids_and_roles_are_reported_at_the_repeat() {
+        let config = Config {
+            excluded_device_ids: vec![SPEAKERS.to_string(), SPEAKERS.to_string()],
+            cycle_roles: vec![DeviceRole::Console, DeviceRole::Console],
+            ..Config::default()
+        };
+        assert_eq!(
+            fields(&validate(&config, None)),
+            vec!["excluded_device_ids[1]", "cycle_roles[1]"]
+        );
+    }
+
+    #[test]
+    fn empty_cycle_roles_mean_every_role() {
+        let config = Config {
+            cycle_roles: Vec::new(),
+            ..Config::default()
+        };
+        assert_eq!(validate(&config, None), vec![]);
+    }
+
+    #[test]
+    fn unknown_and_misdirected_ids_are_reported() {
+        let config = Config {
+            excluded_device_ids: vec![MICROPHONE.to_string(), "gone".to_string()],
+            excluded_capture_device_ids: vec![MICROPHONE.to_string()],
+            ..Config::default()
+        };
+        let errors = validate(&config, Some(&known(&Config::default())));
+        assert_eq!(
+            fields(&errors),
+            vec!["excluded_device_ids[0]", "excluded_device_ids[1]"]
+        );
+        assert_eq!(errors[0].message, "Not an output device");
+        assert_eq!(errors[1].message, "Unknown device");
+    }
+
+    #[test]
+    fn ids_already_saved_stay_valid() {
+        let config = Config {
+            excluded_device_ids: vec!["gone".to_string()],
+            ..Config::default()
+        };
+        assert_eq!(validate(&config, Some(&known(&config))), vec![]);
+    }
+
+    #[test]
+    fn ids_are_unchecked_without_known_devices() {
+        let mut config = Config {
+            excluded_device_ids: vec!["gone".to_string()],
+            ..Config::default()
+        };
+        config.device_mut("gone").max_volume = Some(0.5);
+        assert_eq!(validate(&config, None), vec![]);
+    }
+
+    #[test]
+    fn device_settings_are_range_checked() {
+        let mut config = Config::default();
+        let settings = config.device_mut(SPEAKERS);
+        settings.last_volume = Some(-0.1);
+        settings.max_volume = Some(1.5);
+        settings.preferred_period = Some(0.0);
+        assert_eq!(
+            fields(&validate(&config, Some(&known(&config)))),
+            vec![
+                "devices.speakers.last_volume",
+                "devices.speakers.max_volume",
+                "devices.speakers.preferred_period",
+            ]
+        );
+    }
+
+    #[test]
+    fn listen_target_must_be_a_known_output() {
+        let mut config = Config::default();
+        config.device_mut(MICROPHONE).properties.listen_target = Some(MICROPHONE.to_string());
+        let errors = validate(&config, Some(&known(&Config::default())));
+        assert_eq!(
+            fields(&errors),
+            vec!["devices.microphone.properties.listen_target"]
+        );
+
+        // Empty is the default output
+        config.device_mut(MICROPHONE).properties.listen_target = Some(String::new());
+        assert_eq!(validate(&config, Some(&known(&Config::default()))), vec![]);
+    }
+
+    #[test]
+    fn hidden_devices_need_unique_ids() {
+        let hidden = |id: &str| HiddenDevice {
+            id: id.to_string(),
+            name: "Speakers".to_string(),
+        };
+        let config = Config {
+            hidden_devices: vec![hidden(SPEAKERS), hidden(SPEAKERS), hidden("")],
+            ..Config::default()
+        };
+        assert_eq!(
+            fields(&validate(&config, None)),
+            vec!["hidden_devices[2].id", "hidden_devices[1]"]
+        );
+    }
+}
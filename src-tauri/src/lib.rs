@@ -20,42 +20,44 @@ pub fn run() {
             tauri_plugin_global_shortcut::Builder::new()
                 .with_handler(|app, shortcut, event| {
                     if event.state() == tauri_plugin_global_shortcut::ShortcutState::Pressed {
-                        let matched = {
-                            let state =
-                                app.state::<std::sync::Mutex<crate::config::ConfigManager>>();
-                            let manager = state.lock().unwrap();
-                            let config = manager.load();
-                            if let Some(hotkey_str) = &config.hotkey {
-                                use tauri_plugin_global_shortcut::Shortcut;
-                                if let Ok(config_shortcut) = hotkey_str.parse::<Shortcut>() {
-                                    config_shortcut == *shortcut
-                                } else {
-                                    false
-                                }
-                            } else {
-                                false
-                            }
-                        };
+                        let direction = app.state::<config::AppState>().read(|config| {
+                            use tauri_plugin_global_shortcut::Shortcut;
+                            audio::DeviceDirection::ALL.into_iter().find(|&direction| {
+                                config
+                                    .hotkey(direction)
+                                    .and_then(|hotkey_str| hotkey_str.parse::<Shortcut>().ok())
+                                    .is_some_and(|config_shortcut| config_shortcut == *shortcut)
+                            })
+                        });
 
-                        if matched {
-                            let _ = crate::audio::cycle_next_device(app);
+                        if let Some(direction) = direction {
+                            crate::audio::cycle_in_background(app, direction);
                         }
                     }
                 })
                 .build(),
         )
         .setup(|app| {
-            let config_manager = config::ConfigManager::new(app.handle());
-            let config = config_manager.load();
-            app.manage(std::sync::Mutex::new(config_manager));
+            let app_state = config::AppState::new(config::ConfigManager::new(app.handle()));
+            let config = app_state.config();
+            app.manage(app_state);
 
-            // Register initial hotkey
-            if let Some(hotkey_str) = &config.hotkey {
-                use tauri_plugin_global_shortcut::{GlobalShortcutExt, Shortcut};
-                if let Ok(shortcut) = hotkey_str.parse::<Shortcut>() {
-                    let _ = app.global_shortcut().register(shortcut);
-                }
-            }
+            let backend = audio::create_backend();
+            let tracker = audio::DefaultTracker::new();
+            tracker.seed(backend.as_ref());
+            app.manage(tracker);
+
+            // Managed before subscribing: event handlers look the backend up
+            app.manage(backend.clone());
+            let handle = app.handle().clone();
+            backend.subscribe(Box::new(move |event| {
+                crate::audio::handle_device_event(&handle, event);
+            }));
+            // Reloads are checked against the backend's devices
+            config::watch(app.handle());
+
+            // Register initial hotkeys
+            let _ = commands::register_hotkeys(app.handle(), &config);
 
             #[cfg(desktop)]
             {
@@ -74,8 +76,26 @@ pub fn run() {
         .invoke_handler(tauri::generate_handler![
             commands::get_audio_devices,
             commands::set_active_device,
+            commands::get_volume,
+            commands::set_volume,
+            commands::set_mute,
+            commands::get_device_format,
+            commands::set_device_format,
+            commands::reset_device_format,
+            commands::get_exclusive_mode,
+            commands::set_exclusive_mode,
+            commands::get_processing_period,
+            commands::set_processing_period,
+            commands::get_endpoint_properties,
+            commands::set_endpoint_properties,
+            commands::set_endpoint_visibility,
             commands::get_config,
+            commands::reload_config,
+            commands::take_config_recovery,
             commands::set_excluded_devices,
+            commands::validate_config,
+            commands::export_config,
+            commands::import_config,
             commands::save_config
         ])
         .on_window_event(|window, event| match event {
@@ -91,6 +111,12 @@ pub fn run() {
             }
             _ => {}
         })
-        .run(tauri::generate_context!())
-        .expect("error while running tauri application");
+        .build(tauri::generate_context!())
+        .expect("error while running tauri application")
+        .run(|app, event| {
+            // Remembered volumes may still be waiting to be saved
+            if let tauri::RunEvent::Exit = event {
+                let _ = app.state::<config::AppState>().flush();
+            }
+        });
 }
@@ -29,8 +29,10 @@ pub fn create_tray(app: &AppHandle) -> tauri::Result<()> {
             } = event
             {
                 // Cycle device on SINGLE left click
-                let app_handle = tray.app_handle();
-                let _ = crate::audio::cycle_next_device(app_handle);
+                crate::audio::cycle_in_background(
+                    tray.app_handle(),
+                    crate::audio::DeviceDirection::Render,
+                );
             }
         })
         .icon(app.default_window_icon().unwrap().clone())
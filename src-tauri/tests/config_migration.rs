@@ -0,0 +1,117 @@
+use serde_json::{json, Value};
+use sound_switch_lib::audio::DeviceRole;
+use sound_switch_lib::config::migrate::{self, CURRENT_VERSION};
+use sound_switch_lib::config::Config;
+use std::fs;
+use std::path::Path;
+
+const RENDER_ID: &str = "{0.0.0.00000000}.{a1b2c3d4-0000-0000-0000-000000000001}";
+
+fn fixture(name: &str) -> String {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/fixtures/config")
+        .join(name);
+    fs::read_to_string(&path).unwrap_or_else(|e| panic!("{}: {}", path.display(), e))
+}
+
+fn load(name: &str) -> Config {
+    Config::from_json(&fixture(name)).unwrap_or_else(|e| panic!("{}: {}", name, e))
+}
+
+#[test]
+fn every_historical_version_loads_at_the_current_version() {
+    for name in [
+        "v0-baseline.json",
+        "v0-no-hotkey.json",
+        "v0-capture.json",
+        "v0-devices.json",
+        "v0-null-lists.json",
+        "v0-null-cycle-roles.json",
+        "v1.json",
+    ] {
+        assert_eq!(load(name).version, CURRENT_VERSION, "{}", name);
+    }
+}
+
+#[test]
+fn v0_baseline_keeps_its_values() {
+    let config = load("v0-baseline.json");
+    assert_eq!(config.excluded_device_ids, vec![RENDER_ID.to_string()]);
+    assert_eq!(config.hotkey.as_deref(), Some("CommandOrControl+Alt+S"));
+    assert_eq!(config.cycle_roles, DeviceRole::ALL.to_vec());
+    assert!(config.devices.is_empty());
+    assert!(config.extra.is_empty());
+}
+
+#[test]
+fn v0_without_hotkey_stays_without_one() {
+    let config = load("v0-no-hotkey.json");
+    assert_eq!(config.hotkey, None);
+}
+
+#[test]
+fn v0_capture_fields_are_kept() {
+    let config = load("v0-capture.json");
+    assert_eq!(config.excluded_capture_device_ids.len(), 1);
+    assert_eq!(
+        config.capture_hotkey.as_deref(),
+        Some("CommandOrControl+Shift+M")
+    );
+    assert_eq!(
+        config.cycle_roles,
+        vec![DeviceRole::Console, DeviceRole::Multimedia]
+    );
+}
+
+#[test]
+fn v0_device_settings_are_kept() {
+    let config = load("v0-devices.json");
+    let device = config.device(RENDER_ID);
+    assert!(!device.restore_volume);
+    assert_eq!(device.last_volume, Some(0.4));
+    assert_eq!(device.max_volume, Some(0.8));
+    assert_eq!(device.allow_exclusive, Some(false));
+    assert_eq!(device.preferred_period, Some(3.0));
+    assert_eq!(device.properties.disable_enhancements, Some(true));
+    assert_eq!(config.hidden_devices.len(), 1);
+    assert_eq!(config.hidden_devices[0].name, "Speakers (HDMI)");
+}
+
+#[test]
+fn v0_null_lists_become_empty() {
+    let config = load("v0-null-lists.json");
+    assert!(config.excluded_device_ids.is_empty());
+    assert!(config.excluded_capture_device_ids.is_empty());
+    assert!(config.devices.is_empty());
+    assert!(config.hidden_devices.is_empty());
+}
+
+#[test]
+fn v0_null_cycle_roles_switch_every_role() {
+    let config = load("v0-null-cycle-roles.json");
+    assert_eq!(config.cycle_roles, DeviceRole::ALL.to_vec());
+}
+
+#[test]
+fn current_version_is_left_alone() {
+    let value: Value = serde_json::from_str(&fixture("v1.json")).unwrap();
+    assert_eq!(migrate::migrate(value.clone()).unwrap(), value);
+}
+
+#[test]
+fn newer_version_is_not_downgraded_and_keeps_unknown_fields() {
+    let config = load("v2-future.json");
+    assert_eq!(config.version, 2);
+    assert_eq!(config.extra["theme"], json!("dark"));
+
+    let saved = serde_json::to_value(&config).unwrap();
+    let original: Value = serde_json::from_str(&fixture("v2-future.json")).unwrap();
+    assert_eq!(saved, original);
+}
+
+#[test]
+fn non_object_and_bad_version_are_rejected() {
+    assert!(migrate::migrate(json!([])).is_err());
+    assert!(migrate::migrate(json!({ "version": "one" })).is_err());
+    assert!(migrate::migrate(json!({ "version": -1 })).is_err());
+}
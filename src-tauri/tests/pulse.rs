@@ -0,0 +1,170 @@
+//! Runs the PulseAudio backend against a real server, using null sinks so no
+//! hardware is needed. Ignored by default; with `pulseaudio` or
+//! `pipewire-pulse` running and `pactl` installed, run
+//! `cargo test --test pulse -- --ignored`.
+#![cfg(target_os = "linux")]
+
+use sound_switch_lib::audio::pulse::PulseBackend;
+use sound_switch_lib::audio::{
+    AudioBackend, DeviceDirection, DeviceEvent, DeviceRole, VolumeLevel,
+};
+use std::process::Command;
+use std::sync::mpsc;
+use std::sync::{Mutex, MutexGuard};
+use std::time::Duration;
+
+const SINK_A: &str = "soundswitch_test_a";
+const SINK_B: &str = "soundswitch_test_b";
+
+/// Every test changes the server's default sink, so they take turns.
+static SERVER: Mutex<()> = Mutex::new(());
+
+fn pactl(args: &[&str]) -> String {
+    let output = Command::new("pactl")
+        .args(args)
+        .output()
+        .expect("pactl must be installed");
+    assert!(
+        output.status.success(),
+        "pactl {}: {}",
+        args.join(" "),
+        String::from_utf8_lossy(&output.stderr)
+    );
+    String::from_utf8_lossy(&output.stdout).trim().to_string()
+}
+
+/// Two stereo null sinks, unloaded again along with the previous default
+/// being restored when dropped.
+struct NullSinks {
+    modules: Vec<String>,
+    previous_default: String,
+    _server: MutexGuard<'static, ()>,
+}
+
+impl NullSinks {
+    fn load() -> Self {
+        let server = SERVER.lock().unwrap_or_else(|e| e.into_inner());
+        let previous_default = pactl(&["get-default-sink"]);
+        let modules = [SINK_A, SINK_B]
+            .into_iter()
+            .map(|name| {
+                pactl(&[
+                    "load-module",
+                    "module-null-sink",
+                    &format!("sink_name={}", name),
+                    "channels=2",
+                ])
+            })
+            .collect();
+        Self {
+            modules,
+            previous_default,
+            _server: server,
+        }
+    }
+}
+
+impl Drop for NullSinks {
+    fn drop(&mut self) {
+        let _ = Command::new("pactl")
+            .args(["set-default-sink", &self.previous_default])
+            .status();
+        for module in &self.modules {
+            let _ = Command::new("pactl")
+                .args(["unload-module", module])
+                .status();
+        }
+    }
+}
+
+/// Volumes of each channel of `sink`, in percent.
+fn channel_percents(sink: &str) -> Vec<u32> {
+    pactl(&["get-sink-volume", sink])
+        .split_whitespace()
+        .filter_map(|word| word.strip_suffix('%'))
+        .filter_map(|percent| percent.parse().ok())
+        .collect()
+}
+
+fn wait_for(events: &mpsc::Receiver<DeviceEvent>, matches: impl Fn(&DeviceEvent) -> bool) {
+    loop {
+        let event = events
+            .recv_timeout(Duration::from_secs(5))
+            .expect("no matching event within 5 seconds");
+        if matches(&event) {
+            return;
+        }
+    }
+}
+
+#[test]
+#[ignore = "needs a running PulseAudio server"]
+fn null_sinks_can_be_switched_to() {
+    let _sinks = NullSinks::load();
+    let backend = PulseBackend::new();
+
+    let ids: Vec<String> = backend
+        .enumerate_devices(DeviceDirection::Render)
+        .unwrap()
+        .into_iter()
+        .map(|device| device.id)
+        .collect();
+    assert!(ids.iter().any(|id| id == SINK_A) && ids.iter().any(|id| id == SINK_B));
+
+    for sink in [SINK_A, SINK_B] {
+        backend.set_default_device(sink, &DeviceRole::ALL).unwrap();
+        for role in DeviceRole::ALL {
+            assert_eq!(
+                backend
+                    .default_device(DeviceDirection::Render, role)
+                    .unwrap()
+                    .as_deref(),
+                Some(sink)
+            );
+        }
+    }
+}
+
+#[test]
+#[ignore = "needs a running PulseAudio server"]
+fn set_volume_keeps_the_channel_balance() {
+    let _sinks = NullSinks::load();
+    let backend = PulseBackend::new();
+
+    pactl(&["set-sink-volume", SINK_A, "80%", "40%"]);
+    backend
+        .set_volume(SINK_A, VolumeLevel::Scalar(0.5))
+        .unwrap();
+
+    assert_eq!(channel_percents(SINK_A), vec![50, 25]);
+    let volume = backend.volume(SINK_A).unwrap();
+    assert!((volume.scalar - 0.5).abs() < 0.01, "{}", volume.scalar);
+}
+
+#[test]
+#[ignore = "needs a running PulseAudio server"]
+fn changes_made_elsewhere_are_reported() {
+    let _sinks = NullSinks::load();
+    let backend = PulseBackend::new();
+    let (sender, events) = mpsc::channel();
+    let sender = Mutex::new(sender);
+    backend.subscribe(Box::new(move |event| {
+        let _ = sender.lock().unwrap().send(event);
+    }));
+    // Let the listener connect and read the current volumes
+    std::thread::sleep(Duration::from_millis(500));
+
+    pactl(&["set-default-sink", SINK_B]);
+    wait_for(
+        &events,
+        |event| matches!(event, DeviceEvent::DefaultChanged { id, .. } if id == SINK_B),
+    );
+
+    pactl(&["set-sink-volume", SINK_B, "30%"]);
+    wait_for(&events, |event| match event {
+        DeviceEvent::VolumeChanged { id, volume } => {
+            id == SINK_B && (volume.scalar - 0.3).abs() < 0.01
+        }
+        _ => false,
+    });
+}
@@ -1,32 +1,341 @@
 import { invoke } from "@tauri-apps/api/core";
 
+export type DeviceDirection = "render" | "capture";
+
+export type DeviceRole = "console" | "multimedia" | "communications";
+
+export type DeviceState = "active" | "disabled" | "not_present" | "unplugged";
+
+export type DeviceFormFactor =
+  | "remote_network_device"
+  | "speakers"
+  | "line_level"
+  | "headphones"
+  | "microphone"
+  | "headset"
+  | "handset"
+  | "digital_passthrough"
+  | "spdif"
+  | "hdmi"
+  | "unknown";
+
 export interface AudioDevice {
   id: string;
   name: string;
+  direction: DeviceDirection;
+  state: DeviceState;
   is_default: boolean;
+  default_roles: DeviceRole[];
+  description: string | null;
+  interface_name: string | null;
+  form_factor: DeviceFormFactor;
+  container_id: string | null;
+  icon_path: string | null;
+}
+
+export interface VolumeInfo {
+  /** 0 to 1, on the same curve as the system volume slider. */
+  scalar: number;
+  db: number;
+  muted: boolean;
+}
+
+export type VolumeLevel = { scalar: number } | { db: number };
+
+export interface AudioFormat {
+  sample_rate: number;
+  bit_depth: number;
+  channels: number;
+  float: boolean;
+}
+
+export interface DeviceFormats {
+  current: AudioFormat | null;
+  default: AudioFormat | null;
+  mix: AudioFormat | null;
+}
+
+/** Engine processing periods, in milliseconds. */
+export interface ProcessingPeriod {
+  current: number;
+  default: number;
+  minimum: number;
+}
+
+/** Endpoint settings kept as device properties; null when not reported. */
+export interface EndpointProperties {
+  disable_enhancements: boolean | null;
+  /** "Listen to this device", capture devices only. */
+  listen: boolean | null;
+  /** Render device ID to listen through; "" for the default device. */
+  listen_target: string | null;
 }
 
+/** Payload of the `audio-device-event` Tauri event. */
+export type DeviceEvent =
+  | {
+      kind: "default_changed";
+      id: string;
+      direction: DeviceDirection;
+      role: DeviceRole;
+    }
+  | { kind: "device_added"; id: string }
+  | { kind: "device_removed"; id: string }
+  | { kind: "state_changed"; id: string; state: DeviceState }
+  | { kind: "volume_changed"; id: string; volume: VolumeInfo }
+  | { kind: "devices_changed" };
+
+/** Payload of the `device-changed` Tauri event. */
+export interface DeviceChanged {
+  reason: "cycle" | "external";
+  direction: DeviceDirection;
+  id: string;
+}
+
+/** Payload of the `volume-capped` Tauri event. */
+export interface VolumeCapped {
+  id: string;
+  /** The volume before it was lowered. */
+  volume: number;
+  max_volume: number;
+}
+
+/**
+ * Error of `setActiveDevice` and payload of the `switch-failed` Tauri event.
+ * `not_applied` means the driver accepted the switch but ignored it for
+ * `roles`.
+ */
+export type SwitchError =
+  | { kind: "failed"; message: string }
+  | { kind: "not_applied"; id: string; roles: DeviceRole[] };
+
+/** A problem with one config field, e.g. `hotkey` or `devices.<id>.max_volume`. */
+export interface FieldError {
+  field: string;
+  message: string;
+}
+
+/**
+ * Error of `saveConfig`. `invalid` means nothing was saved because of
+ * `errors`.
+ */
+export type ConfigError =
+  | { kind: "invalid"; errors: FieldError[] }
+  | { kind: "failed"; message: string };
+
 export interface Config {
+  /** Schema version; older files are migrated on load. */
+  version: number;
   excluded_device_ids: string[];
   hotkey: string | null;
+  excluded_capture_device_ids: string[];
+  capture_hotkey: string | null;
+  cycle_roles: DeviceRole[];
+  /** Keyed by device ID; missing devices use the defaults. */
+  devices: Record<string, DeviceSettings>;
+  /** Devices hidden from the whole system. */
+  hidden_devices: HiddenDevice[];
+}
+
+export interface HiddenDevice {
+  id: string;
+  name: string;
+}
+
+export interface DeviceSettings {
+  restore_volume: boolean;
+  last_volume: number | null;
+  /** Highest allowed volume (0 to 1), or null for no cap. */
+  max_volume: number | null;
+  /** Applied whenever the device is selected; null leaves Windows alone. */
+  allow_exclusive: boolean | null;
+  /** Engine period (ms) applied whenever the device is selected. */
+  preferred_period: number | null;
+  /** Properties written whenever the device is selected. */
+  properties: EndpointProperties;
+}
+
+/** Settings of a device without an entry, as `DeviceSettings::default()` in Rust. */
+export function defaultDeviceSettings(): DeviceSettings {
+  return {
+    restore_volume: true,
+    last_volume: null,
+    max_volume: null,
+    allow_exclusive: null,
+    preferred_period: null,
+    properties: {
+      disable_enhancements: null,
+      listen: null,
+      listen_target: null,
+    },
+  };
+}
+
+export async function getAudioDevices(
+  direction?: DeviceDirection
+): Promise<AudioDevice[]> {
+  return invoke("get_audio_devices", { direction });
+}
+
+export async function setActiveDevice(
+  id: string,
+  roles?: DeviceRole[]
+): Promise<void> {
+  return invoke("set_active_device", { id, roles });
+}
+
+export async function getVolume(id: string): Promise<VolumeInfo> {
+  return invoke("get_volume", { id });
+}
+
+export async function setVolume(id: string, level: VolumeLevel): Promise<void> {
+  return invoke("set_volume", { id, level });
+}
+
+export async function setMute(id: string, muted: boolean): Promise<void> {
+  return invoke("set_mute", { id, muted });
+}
+
+export async function getDeviceFormat(id: string): Promise<DeviceFormats> {
+  return invoke("get_device_format", { id });
+}
+
+export async function setDeviceFormat(
+  id: string,
+  format: AudioFormat
+): Promise<void> {
+  return invoke("set_device_format", { id, format });
+}
+
+export async function resetDeviceFormat(id: string): Promise<void> {
+  return invoke("reset_device_format", { id });
+}
+
+export async function getExclusiveMode(id: string): Promise<boolean> {
+  return invoke("get_exclusive_mode", { id });
+}
+
+/** Applies the setting and remembers it for the device. */
+export async function setExclusiveMode(
+  id: string,
+  allowed: boolean
+): Promise<void> {
+  return invoke("set_exclusive_mode", { id, allowed });
 }
 
-export async function getAudioDevices(): Promise<AudioDevice[]> {
-  return invoke("get_audio_devices");
+export async function getProcessingPeriod(
+  id: string
+): Promise<ProcessingPeriod> {
+  return invoke("get_processing_period", { id });
 }
 
-export async function setActiveDevice(id: string): Promise<void> {
-  return invoke("set_active_device", { id });
+/** Applies and remembers a period; null returns to the default. */
+export async function setProcessingPeriod(
+  id: string,
+  period: number | null
+): Promise<void> {
+  return invoke("set_processing_period", { id, period });
+}
+
+export async function getEndpointProperties(
+  id: string
+): Promise<EndpointProperties> {
+  return invoke("get_endpoint_properties", { id });
+}
+
+/** Writes the non-null fields and remembers them for the device. */
+export async function setEndpointProperties(
+  id: string,
+  properties: Partial<EndpointProperties>
+): Promise<void> {
+  return invoke("set_endpoint_properties", {
+    id,
+    properties: {
+      disable_enhancements: null,
+      listen: null,
+      listen_target: null,
+      ...properties,
+    },
+  });
+}
+
+/** Shows or hides a device system-wide, tracking it in `hidden_devices`. */
+export async function setEndpointVisibility(
+  id: string,
+  visible: boolean
+): Promise<void> {
+  return invoke("set_endpoint_visibility", { id, visible });
 }
 
 export async function getConfig(): Promise<Config> {
   return invoke("get_config");
 }
 
-export async function setExcludedDevices(ids: string[]): Promise<void> {
-  return invoke("set_excluded_devices", { ids });
+/** What was done about a config.json that failed to read or parse at startup. */
+export interface ConfigRecovery {
+  error: string;
+  /** The file couldn't be read at all; it was left where it is. */
+  unreadable: boolean;
+  /** Where the bad file was moved to. */
+  quarantined_to: string | null;
+  /** The backup restored; null when the defaults are in use. */
+  restored_from: string | null;
+}
+
+/** The startup recovery, if any. Returned only once. */
+export async function takeConfigRecovery(): Promise<ConfigRecovery | null> {
+  return invoke("take_config_recovery");
+}
+
+/**
+ * Re-reads config.json, for edits made outside the app. Rejects with a
+ * `ConfigError` when the file is invalid.
+ */
+export async function reloadConfig(): Promise<Config> {
+  return invoke("reload_config");
+}
+
+export async function setExcludedDevices(
+  ids: string[],
+  direction?: DeviceDirection
+): Promise<void> {
+  return invoke("set_excluded_devices", { ids, direction });
+}
+
+/** Returns the problems `saveConfig` would reject `config` for. */
+export async function validateConfig(config: Config): Promise<FieldError[]> {
+  return invoke("validate_config", { config });
 }
 
 export async function saveConfig(config: Config): Promise<void> {
   return invoke("save_config", { config });
 }
+
+/** `merge` adds to the current settings, `replace` swaps them out. */
+export type ImportMode = "merge" | "replace";
+
+/** A device an imported file mentions that this machine doesn't have. */
+export interface MissingDevice {
+  id: string;
+  /** Name on the machine that exported it. */
+  name: string | null;
+}
+
+export interface ImportReport {
+  missing_devices: MissingDevice[];
+}
+
+/** Asks for a file and writes the settings to it. Null when cancelled. */
+export async function exportConfig(): Promise<string | null> {
+  return invoke("export_config");
+}
+
+/**
+ * Asks for a file written by `exportConfig` and imports it. Rejects with a
+ * `ConfigError`; null when cancelled.
+ */
+export async function importConfig(
+  mode: ImportMode
+): Promise<ImportReport | null> {
+  return invoke("import_config", { mode });
+}